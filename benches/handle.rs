@@ -0,0 +1,27 @@
+// 对比全局函数 (每次 OnceLock::get) 与先取 handle 再直接调用 CacheClient 的开销
+// cargo bench --bench handle
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use moka_cache::Expiration;
+
+fn handle(c: &mut Criterion) {
+    moka_cache::setup(None, 1024).unwrap();
+    moka_cache::insert("k", 42u64, Expiration::Never).unwrap();
+    let h = moka_cache::handle().unwrap();
+
+    let mut group = c.benchmark_group("get_u64");
+    group.bench_function("global", |b| {
+        b.iter(|| moka_cache::get::<_, u64>(black_box("k")))
+    });
+    group.bench_function("handle", |b| b.iter(|| h.get::<_, u64>(black_box("k"))));
+    group.finish();
+
+    let mut group = c.benchmark_group("contains_key");
+    group.bench_function("global", |b| {
+        b.iter(|| moka_cache::contains_key(black_box("k")))
+    });
+    group.bench_function("handle", |b| b.iter(|| h.contains_key(black_box("k"))));
+    group.finish();
+}
+
+criterion_group!(benches, handle);
+criterion_main!(benches);
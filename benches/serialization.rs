@@ -0,0 +1,108 @@
+// 对比 bincode / JSON / MessagePack 的编码、解码吞吐
+// cargo bench --bench serialization --features msgpack
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Small {
+    id: u64,
+    name: String,
+    enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Large {
+    id: u64,
+    tags: Vec<String>,
+    items: Vec<Small>,
+    attrs: BTreeMap<String, u32>,
+}
+
+fn small() -> Small {
+    Small {
+        id: 42,
+        name: "moka-cache".to_string(),
+        enabled: true,
+    }
+}
+
+fn large() -> Large {
+    Large {
+        id: 7,
+        tags: (0..32).map(|i| format!("tag-{i}")).collect(),
+        items: (0..256)
+            .map(|i| Small {
+                id: i,
+                name: format!("item-{i}"),
+                enabled: i % 2 == 0,
+            })
+            .collect(),
+        attrs: (0..64).map(|i| (format!("attr-{i}"), i)).collect(),
+    }
+}
+
+type Encode<T> = fn(&T) -> Vec<u8>;
+type Decode<T> = fn(&[u8]) -> T;
+
+fn codecs<T: Serialize + DeserializeOwned>() -> Vec<(&'static str, Encode<T>, Decode<T>)> {
+    #[allow(unused_mut)]
+    let mut v: Vec<(&'static str, Encode<T>, Decode<T>)> = vec![
+        (
+            "bincode_standard",
+            |v| bincode::serde::encode_to_vec(v, bincode::config::standard()).unwrap(),
+            |b| {
+                bincode::serde::decode_from_slice(b, bincode::config::standard())
+                    .unwrap()
+                    .0
+            },
+        ),
+        (
+            "bincode_legacy",
+            |v| bincode::serde::encode_to_vec(v, bincode::config::legacy()).unwrap(),
+            |b| {
+                bincode::serde::decode_from_slice(b, bincode::config::legacy())
+                    .unwrap()
+                    .0
+            },
+        ),
+        (
+            "serde_json",
+            |v| serde_json::to_vec(v).unwrap(),
+            |b| serde_json::from_slice(b).unwrap(),
+        ),
+    ];
+    #[cfg(feature = "msgpack")]
+    v.push((
+        "rmp_serde",
+        |v| rmp_serde::to_vec(v).unwrap(),
+        |b| rmp_serde::from_slice(b).unwrap(),
+    ));
+    v
+}
+
+fn bench_type<T: Serialize + DeserializeOwned>(c: &mut Criterion, name: &str, value: T) {
+    let mut group = c.benchmark_group(name);
+    for (codec, encode, decode) in codecs::<T>() {
+        let bytes = encode(&value);
+        println!("{name}/{codec}: payload {} bytes", bytes.len());
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("encode", codec), &value, |b, v| {
+            b.iter(|| encode(black_box(v)))
+        });
+        group.bench_with_input(BenchmarkId::new("decode", codec), &bytes, |b, v| {
+            b.iter(|| decode(black_box(v)))
+        });
+    }
+    group.finish();
+}
+
+fn serialization(c: &mut Criterion) {
+    bench_type(c, "small_struct", small());
+    bench_type(c, "large_struct", large());
+    bench_type(c, "bytes", vec![7u8; 16 * 1024]);
+    bench_type(c, "string", "moka-cache ".repeat(1024));
+}
+
+criterion_group!(benches, serialization);
+criterion_main!(benches);
@@ -0,0 +1,481 @@
+// 确定性序列化: 先把 value 转成中间结构, 按 key 的 bincode 编码字节排序所有 map 后再交给编码器.
+// HashMap/HashSet 的遍历顺序每个实例都不同, 直接编码时相等的值可能得到不同的字节;
+// 排序后同一个逻辑值总是得到同样的字节, 解码结果不变.
+// Vec、struct、enum、BTreeMap 等本身就是确定的, 只有 map (serialize_map) 会被重排,
+// HashSet 走 serialize_seq, 无法与 Vec 区分, 不会被排序
+use bincode::{config, error::EncodeError};
+use serde::ser::{self, Serialize, Serializer};
+use std::fmt;
+
+// 返回的 Value 实现了 Serialize, 可以交给任意 serde 编码器
+pub(crate) fn to_value<V: Serialize + ?Sized>(value: &V) -> Result<Value, EncodeError> {
+    let mut v = value
+        .serialize(ValueSerializer)
+        .map_err(|e| EncodeError::OtherString(e.0))?;
+    v.canonicalize()?;
+    Ok(v)
+}
+
+#[derive(Debug)]
+pub(crate) struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+type Name = &'static str;
+
+pub(crate) enum Value {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    Str(String),
+    Bytes(Vec<u8>),
+    None,
+    Some(Box<Value>),
+    Unit,
+    UnitStruct(Name),
+    UnitVariant(Name, u32, Name),
+    NewtypeStruct(Name, Box<Value>),
+    NewtypeVariant(Name, u32, Name, Box<Value>),
+    Seq(Vec<Value>),
+    Tuple(Vec<Value>),
+    TupleStruct(Name, Vec<Value>),
+    TupleVariant(Name, u32, Name, Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Struct(Name, Vec<(Name, Value)>),
+    StructVariant(Name, u32, Name, Vec<(Name, Value)>),
+}
+
+impl Value {
+    fn canonicalize(&mut self) -> Result<(), EncodeError> {
+        match self {
+            Value::Some(v) | Value::NewtypeStruct(_, v) | Value::NewtypeVariant(_, _, _, v) => {
+                v.canonicalize()
+            }
+            Value::Seq(vs)
+            | Value::Tuple(vs)
+            | Value::TupleStruct(_, vs)
+            | Value::TupleVariant(_, _, _, vs) => vs.iter_mut().try_for_each(|v| v.canonicalize()),
+            Value::Struct(_, fs) | Value::StructVariant(_, _, _, fs) => {
+                fs.iter_mut().try_for_each(|(_, v)| v.canonicalize())
+            }
+            Value::Map(entries) => {
+                let mut keyed = Vec::with_capacity(entries.len());
+                for (mut k, mut v) in entries.drain(..) {
+                    k.canonicalize()?;
+                    v.canonicalize()?;
+                    let b = bincode::serde::encode_to_vec(&k, config::standard())?;
+                    keyed.push((b, k, v));
+                }
+                keyed.sort_by(|a, b| a.0.cmp(&b.0));
+                entries.extend(keyed.into_iter().map(|(_, k, v)| (k, v)));
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use ser::{
+            SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+            SerializeTupleStruct, SerializeTupleVariant,
+        };
+        match self {
+            Value::Bool(v) => s.serialize_bool(*v),
+            Value::I8(v) => s.serialize_i8(*v),
+            Value::I16(v) => s.serialize_i16(*v),
+            Value::I32(v) => s.serialize_i32(*v),
+            Value::I64(v) => s.serialize_i64(*v),
+            Value::I128(v) => s.serialize_i128(*v),
+            Value::U8(v) => s.serialize_u8(*v),
+            Value::U16(v) => s.serialize_u16(*v),
+            Value::U32(v) => s.serialize_u32(*v),
+            Value::U64(v) => s.serialize_u64(*v),
+            Value::U128(v) => s.serialize_u128(*v),
+            Value::F32(v) => s.serialize_f32(*v),
+            Value::F64(v) => s.serialize_f64(*v),
+            Value::Char(v) => s.serialize_char(*v),
+            Value::Str(v) => s.serialize_str(v),
+            Value::Bytes(v) => s.serialize_bytes(v),
+            Value::None => s.serialize_none(),
+            Value::Some(v) => s.serialize_some(v),
+            Value::Unit => s.serialize_unit(),
+            Value::UnitStruct(name) => s.serialize_unit_struct(name),
+            Value::UnitVariant(name, idx, variant) => s.serialize_unit_variant(name, *idx, variant),
+            Value::NewtypeStruct(name, v) => s.serialize_newtype_struct(name, v),
+            Value::NewtypeVariant(name, idx, variant, v) => {
+                s.serialize_newtype_variant(name, *idx, variant, v)
+            }
+            Value::Seq(vs) => {
+                let mut seq = s.serialize_seq(Some(vs.len()))?;
+                for v in vs {
+                    seq.serialize_element(v)?;
+                }
+                seq.end()
+            }
+            Value::Tuple(vs) => {
+                let mut t = s.serialize_tuple(vs.len())?;
+                for v in vs {
+                    t.serialize_element(v)?;
+                }
+                t.end()
+            }
+            Value::TupleStruct(name, vs) => {
+                let mut t = s.serialize_tuple_struct(name, vs.len())?;
+                for v in vs {
+                    t.serialize_field(v)?;
+                }
+                t.end()
+            }
+            Value::TupleVariant(name, idx, variant, vs) => {
+                let mut t = s.serialize_tuple_variant(name, *idx, variant, vs.len())?;
+                for v in vs {
+                    t.serialize_field(v)?;
+                }
+                t.end()
+            }
+            Value::Map(entries) => {
+                let mut m = s.serialize_map(Some(entries.len()))?;
+                for (k, v) in entries {
+                    m.serialize_entry(k, v)?;
+                }
+                m.end()
+            }
+            Value::Struct(name, fs) => {
+                let mut st = s.serialize_struct(name, fs.len())?;
+                for (f, v) in fs {
+                    st.serialize_field(f, v)?;
+                }
+                st.end()
+            }
+            Value::StructVariant(name, idx, variant, fs) => {
+                let mut st = s.serialize_struct_variant(name, *idx, variant, fs.len())?;
+                for (f, v) in fs {
+                    st.serialize_field(f, v)?;
+                }
+                st.end()
+            }
+        }
+    }
+}
+
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SeqBuilder;
+    type SerializeTuple = SeqBuilder;
+    type SerializeTupleStruct = SeqBuilder;
+    type SerializeTupleVariant = SeqBuilder;
+    type SerializeMap = MapBuilder;
+    type SerializeStruct = StructBuilder;
+    type SerializeStructVariant = StructBuilder;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::I8(v))
+    }
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::I16(v))
+    }
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::I32(v))
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::I64(v))
+    }
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        Ok(Value::I128(v))
+    }
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::U8(v))
+    }
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::U16(v))
+    }
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::U32(v))
+    }
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::U64(v))
+    }
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        Ok(Value::U128(v))
+    }
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::F32(v))
+    }
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::F64(v))
+    }
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Char(v))
+    }
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::Str(v.to_string()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Bytes(v.to_vec()))
+    }
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::None)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, v: &T) -> Result<Value, Error> {
+        Ok(Value::Some(Box::new(v.serialize(ValueSerializer)?)))
+    }
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Unit)
+    }
+    fn serialize_unit_struct(self, name: Name) -> Result<Value, Error> {
+        Ok(Value::UnitStruct(name))
+    }
+    fn serialize_unit_variant(self, name: Name, idx: u32, variant: Name) -> Result<Value, Error> {
+        Ok(Value::UnitVariant(name, idx, variant))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: Name,
+        v: &T,
+    ) -> Result<Value, Error> {
+        Ok(Value::NewtypeStruct(
+            name,
+            Box::new(v.serialize(ValueSerializer)?),
+        ))
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: Name,
+        idx: u32,
+        variant: Name,
+        v: &T,
+    ) -> Result<Value, Error> {
+        Ok(Value::NewtypeVariant(
+            name,
+            idx,
+            variant,
+            Box::new(v.serialize(ValueSerializer)?),
+        ))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqBuilder, Error> {
+        Ok(SeqBuilder::new(SeqKind::Seq, len.unwrap_or(0)))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqBuilder, Error> {
+        Ok(SeqBuilder::new(SeqKind::Tuple, len))
+    }
+    fn serialize_tuple_struct(self, name: Name, len: usize) -> Result<SeqBuilder, Error> {
+        Ok(SeqBuilder::new(SeqKind::TupleStruct(name), len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        name: Name,
+        idx: u32,
+        variant: Name,
+        len: usize,
+    ) -> Result<SeqBuilder, Error> {
+        Ok(SeqBuilder::new(
+            SeqKind::TupleVariant(name, idx, variant),
+            len,
+        ))
+    }
+    fn serialize_map(self, len: Option<usize>) -> Result<MapBuilder, Error> {
+        Ok(MapBuilder {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+    fn serialize_struct(self, name: Name, len: usize) -> Result<StructBuilder, Error> {
+        Ok(StructBuilder {
+            variant: None,
+            name,
+            fields: Vec::with_capacity(len),
+        })
+    }
+    fn serialize_struct_variant(
+        self,
+        name: Name,
+        idx: u32,
+        variant: Name,
+        len: usize,
+    ) -> Result<StructBuilder, Error> {
+        Ok(StructBuilder {
+            variant: Some((idx, variant)),
+            name,
+            fields: Vec::with_capacity(len),
+        })
+    }
+}
+
+enum SeqKind {
+    Seq,
+    Tuple,
+    TupleStruct(Name),
+    TupleVariant(Name, u32, Name),
+}
+
+struct SeqBuilder {
+    kind: SeqKind,
+    items: Vec<Value>,
+}
+
+impl SeqBuilder {
+    fn new(kind: SeqKind, len: usize) -> Self {
+        Self {
+            kind,
+            items: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.items.push(v.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.kind {
+            SeqKind::Seq => Value::Seq(self.items),
+            SeqKind::Tuple => Value::Tuple(self.items),
+            SeqKind::TupleStruct(name) => Value::TupleStruct(name, self.items),
+            SeqKind::TupleVariant(name, idx, variant) => {
+                Value::TupleVariant(name, idx, variant, self.items)
+            }
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        self.push(v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+struct MapBuilder {
+    entries: Vec<(Value, Value)>,
+    key: Option<Value>,
+}
+
+impl ser::SerializeMap for MapBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, k: &T) -> Result<(), Error> {
+        self.key = Some(k.serialize(ValueSerializer)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, v: &T) -> Result<(), Error> {
+        let k = self
+            .key
+            .take()
+            .ok_or_else(|| Error("serialize_value called before serialize_key".to_string()))?;
+        self.entries.push((k, v.serialize(ValueSerializer)?));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+struct StructBuilder {
+    variant: Option<(u32, Name)>,
+    name: Name,
+    fields: Vec<(Name, Value)>,
+}
+
+impl StructBuilder {
+    fn push<T: Serialize + ?Sized>(&mut self, f: Name, v: &T) -> Result<(), Error> {
+        self.fields.push((f, v.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            None => Value::Struct(self.name, self.fields),
+            Some((idx, variant)) => Value::StructVariant(self.name, idx, variant, self.fields),
+        })
+    }
+}
+
+impl ser::SerializeStruct for StructBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, f: Name, v: &T) -> Result<(), Error> {
+        self.push(f, v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructBuilder {
+    type Ok = Value;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, f: Name, v: &T) -> Result<(), Error> {
+        self.push(f, v)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
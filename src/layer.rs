@@ -0,0 +1,103 @@
+use crate::Expiration;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+// 从请求计算缓存 key, 返回 None 表示该请求不走缓存
+pub trait KeyExtractor<Request> {
+    fn extract(&self, req: &Request) -> Option<String>;
+}
+
+// 用闭包实现 KeyExtractor
+#[derive(Clone)]
+pub struct KeyExtractorFn<F>(pub F);
+
+impl<Request, F> KeyExtractor<Request> for KeyExtractorFn<F>
+where
+    F: Fn(&Request) -> Option<String>,
+{
+    fn extract(&self, req: &Request) -> Option<String> {
+        (self.0)(req)
+    }
+}
+
+// 缓存 Service 的响应, 响应需要可序列化; 使用 setup 初始化的全局缓存
+#[derive(Clone)]
+pub struct CacheLayer<E> {
+    extractor: E,
+    exp: Expiration,
+}
+
+impl<E> CacheLayer<E> {
+    pub fn new(extractor: E) -> Self {
+        Self {
+            extractor,
+            exp: Expiration::Never,
+        }
+    }
+
+    pub fn with_expiration(mut self, exp: Expiration) -> Self {
+        self.exp = exp;
+        self
+    }
+}
+
+impl<S, E: Clone> Layer<S> for CacheLayer<E> {
+    type Service = CacheService<S, E>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService {
+            inner,
+            extractor: self.extractor.clone(),
+            exp: self.exp,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheService<S, E> {
+    inner: S,
+    extractor: E,
+    exp: Expiration,
+}
+
+impl<S, E, Request> Service<Request> for CacheService<S, E>
+where
+    S: Service<Request>,
+    S::Future: Send + 'static,
+    S::Response: Serialize + DeserializeOwned + Sync + Send + 'static,
+    E: KeyExtractor<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let key = self.extractor.extract(&req);
+        if let Some(k) = &key {
+            if let Some((_, resp)) = crate::get::<_, S::Response>(k.as_str()) {
+                return Box::pin(async move { Ok(resp) });
+            }
+        }
+
+        let fut = self.inner.call(req);
+        let exp = self.exp;
+        Box::pin(async move {
+            let resp = fut.await?;
+            if let Some(k) = key {
+                if let Err(e) = crate::insert(k, &resp, exp) {
+                    log::error!("cache layer insert error: {}", e);
+                }
+            }
+            Ok(resp)
+        })
+    }
+}
@@ -1,17 +1,28 @@
 #![allow(non_upper_case_globals)]
 use anyhow::{anyhow, Result};
-use bincode::config;
+use bincode::{
+    config,
+    error::{DecodeError, EncodeError},
+};
+#[cfg(feature = "bytes")]
+pub use bytes::Bytes;
+pub use either::Either;
 pub use moka::notification::RemovalCause;
+pub use moka::policy::EvictionPolicy;
 use moka::{sync::Cache, Expiry};
 #[allow(unused_imports)]
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    sync::Arc,
+    collections::HashMap,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
     sync::OnceLock,
+    sync::{Arc, Mutex, RwLock},
     time::{Duration, Instant},
 };
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+// PartialEq/Eq/Hash 按结构比较: Second(60) != Minute(1), get 等接口返回的都是写入时的原值;
+// 按时长比较用 same_duration, 或先 normalized() 再比较 / 作为 map 的 key
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub enum Expiration {
     Never,
     Millis(u64),
@@ -24,223 +35,7831 @@ impl Expiration {
     pub fn as_duration(&self) -> Option<Duration> {
         match self {
             Expiration::Never => None,
-            Expiration::Millis(v) => Some(Duration::from_millis(v.clone())),
-            Expiration::Second(v) => Some(Duration::from_secs(v.clone())),
-            Expiration::Minute(v) => Some(Duration::from_secs(v.clone() * 60)),
-            Expiration::Hour(v) => Some(Duration::from_secs(v.clone() * 60 * 60)),
+            Expiration::Millis(v) => Some(Duration::from_millis(*v)),
+            Expiration::Second(v) => Some(Duration::from_secs(*v)),
+            // 超出 u64 秒数时饱和, 避免溢出 panic
+            Expiration::Minute(v) => Some(Duration::from_secs(v.saturating_mul(60))),
+            Expiration::Hour(v) => Some(Duration::from_secs(v.saturating_mul(60 * 60))),
         }
     }
-}
 
-pub struct CacheExpiry;
+    pub fn kind(&self) -> ExpirationKind {
+        match self {
+            Expiration::Never => ExpirationKind::Permanent,
+            _ => ExpirationKind::Timed,
+        }
+    }
 
-pub type CacheData = (Expiration, Vec<u8>);
+    // 归一化为能整除的最大单位: Second(60) -> Minute(1), Millis(2000) -> Second(2);
+    // 时长相同的 Expiration 归一化后相等, 0 归一为 Hour(0)
+    pub fn normalized(&self) -> Expiration {
+        let ms: u128 = match *self {
+            Expiration::Never => return Expiration::Never,
+            Expiration::Millis(v) => v.into(),
+            Expiration::Second(v) => u128::from(v) * 1000,
+            Expiration::Minute(v) => u128::from(v) * 60 * 1000,
+            Expiration::Hour(v) => u128::from(v) * 60 * 60 * 1000,
+        };
+        let hour = 60 * 60 * 1000;
+        let minute = 60 * 1000;
+        let fits = |unit: u128| {
+            ms.is_multiple_of(unit)
+                .then(|| u64::try_from(ms / unit).ok())
+                .flatten()
+        };
+        if let Some(v) = fits(hour) {
+            return Expiration::Hour(v);
+        }
+        if let Some(v) = fits(minute) {
+            return Expiration::Minute(v);
+        }
+        if let Some(v) = fits(1000) {
+            return Expiration::Second(v);
+        }
+        Expiration::Millis(u64::try_from(ms).unwrap_or(u64::MAX))
+    }
 
-type AppCache = Cache<String, (Expiration, Vec<u8>)>;
+    // 按时长比较, 不区分单位
+    pub fn same_duration(&self, other: &Expiration) -> bool {
+        self.normalized() == other.normalized()
+    }
 
-impl Expiry<String, (Expiration, Vec<u8>)> for CacheExpiry {
-    #[allow(unused_variables)]
-    fn expire_after_create(
-        &self,
-        key: &String,
-        value: &(Expiration, Vec<u8>),
-        current_time: Instant,
-    ) -> Option<Duration> {
-        value.0.as_duration()
+    // 延长 extra, 溢出返回 None; Never 仍为 Never.
+    // extra 是当前单位的整数倍时保持原单位, 否则换成 Second 或 Millis (不足 1ms 的部分舍去)
+    pub fn checked_add(&self, extra: Duration) -> Option<Expiration> {
+        let (v, unit_secs) = match *self {
+            Expiration::Never => return Some(Expiration::Never),
+            Expiration::Millis(v) => {
+                if extra.subsec_nanos().is_multiple_of(1_000_000) {
+                    let add = u64::try_from(extra.as_millis()).ok()?;
+                    return Some(Expiration::Millis(v.checked_add(add)?));
+                }
+                let total = Duration::from_millis(v).checked_add(extra)?;
+                return Some(Expiration::Millis(u64::try_from(total.as_millis()).ok()?));
+            }
+            Expiration::Second(v) => (v, 1),
+            Expiration::Minute(v) => (v, 60),
+            Expiration::Hour(v) => (v, 60 * 60),
+        };
+        if extra.subsec_nanos() == 0 && extra.as_secs().is_multiple_of(unit_secs) {
+            let v = v.checked_add(extra.as_secs() / unit_secs)?;
+            return Some(match self {
+                Expiration::Second(_) => Expiration::Second(v),
+                Expiration::Minute(_) => Expiration::Minute(v),
+                _ => Expiration::Hour(v),
+            });
+        }
+        let total = Duration::from_secs(v.checked_mul(unit_secs)?).checked_add(extra)?;
+        if total.subsec_nanos() == 0 {
+            return Some(Expiration::Second(total.as_secs()));
+        }
+        Some(Expiration::Millis(u64::try_from(total.as_millis()).ok()?))
     }
-}
 
-static CacheHand: OnceLock<AppCache> = OnceLock::new();
+    // 延长 extra, 溢出时饱和为当前单位的最大值
+    pub fn saturating_add(&self, extra: Duration) -> Expiration {
+        self.checked_add(extra).unwrap_or(match self {
+            Expiration::Never => Expiration::Never,
+            Expiration::Millis(_) => Expiration::Millis(u64::MAX),
+            Expiration::Second(_) => Expiration::Second(u64::MAX),
+            Expiration::Minute(_) => Expiration::Minute(u64::MAX),
+            Expiration::Hour(_) => Expiration::Hour(u64::MAX),
+        })
+    }
 
-//初始化缓存
-pub fn setup(
-    callback: Option<fn(Arc<String>, CacheData, RemovalCause)>,
-    max_cap: u64,
-) -> Result<()> {
-    let mut c = Cache::builder()
-        .max_capacity(max_cap)
-        .expire_after(CacheExpiry {});
+    // 整秒用 Second, 否则用 Millis (不足 1ms 的部分舍去); Duration::ZERO 视为 Never
+    pub fn from_duration(d: Duration) -> Self {
+        if d.is_zero() {
+            log::debug!("zero duration expiration, treat as Expiration::Never");
+            return Expiration::Never;
+        }
+        if d.subsec_nanos() == 0 {
+            return Expiration::Second(d.as_secs());
+        }
+        Expiration::Millis(u64::try_from(d.as_millis()).unwrap_or(u64::MAX))
+    }
+}
+
+// 过期时间的类别: 只关心是否会过期, 不关心具体时长
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum ExpirationKind {
+    Timed,
+    Permanent,
+}
 
-    if let Some(callback) = callback {
-        c = c.eviction_listener(callback);
+impl From<Duration> for Expiration {
+    fn from(d: Duration) -> Self {
+        Expiration::from_duration(d)
     }
-    let c = c.build();
-    CacheHand
-        .set(c)
-        .map_err(|e| anyhow!("setup cache error:{:?}", e))?;
-    Ok(())
 }
 
-// pub fn insert<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
-// where
-//     K: Into<String>,
-//     V: Serialize + Encode + Sync + Send,
-// {
-//     let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
-//     let k = key.into();
-//     let b = bincode::encode_to_vec(&value, config::standard())?;
-//     cache.insert(k, (exp, b));
-//     Ok(())
-// }
+// 过期时间: None 表示永不过期, Some(Duration::ZERO) 表示立即过期
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Ttl(Option<Duration>);
 
-pub fn insert<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
-where
-    K: Into<String>,
-    V: Serialize + Sync + Send,
-{
-    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
-    let k = key.into();
-    let b = bincode::serde::encode_to_vec(&value, config::standard())?;
-    cache.insert(k, (exp, b));
-    Ok(())
+impl Ttl {
+    pub const NEVER: Ttl = Ttl(None);
+
+    pub const fn new(d: Duration) -> Self {
+        Ttl(Some(d))
+    }
+
+    pub const fn millis(v: u64) -> Self {
+        Ttl(Some(Duration::from_millis(v)))
+    }
+
+    pub const fn seconds(v: u64) -> Self {
+        Ttl(Some(Duration::from_secs(v)))
+    }
+
+    pub const fn minutes(v: u64) -> Self {
+        Ttl(Some(Duration::from_secs(v.saturating_mul(60))))
+    }
+
+    pub const fn hours(v: u64) -> Self {
+        Ttl(Some(Duration::from_secs(v.saturating_mul(60 * 60))))
+    }
+
+    pub fn is_never(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub fn as_duration(&self) -> Option<Duration> {
+        self.0
+    }
 }
 
-pub fn get<K, V>(key: K) -> Option<(Expiration, V)>
-where
-    K: Into<String>,
-    V: DeserializeOwned + Sync + Send,
-{
-    if let Some(h) = CacheHand.get() {
-        let k = key.into();
-        let v = h.get(&k)?;
-        let c = config::standard();
-        let b = bincode::serde::decode_from_slice::<V, _>(v.1.as_ref(), c);
-        if let Ok((value, _)) = b {
-            return Some((v.0, value));
+impl From<Expiration> for Ttl {
+    fn from(exp: Expiration) -> Self {
+        Ttl(exp.as_duration())
+    }
+}
+
+impl From<Ttl> for Expiration {
+    // 整秒用 Second, 否则用 Millis (不足 1ms 的部分舍去)
+    fn from(ttl: Ttl) -> Self {
+        match ttl.0 {
+            None => Expiration::Never,
+            Some(d) if d.subsec_nanos() == 0 => Expiration::Second(d.as_secs()),
+            Some(d) => Expiration::Millis(u64::try_from(d.as_millis()).unwrap_or(u64::MAX)),
         }
-        if let Err(e) = b {
-            log::error!("cache deserialize error: {}", e.to_string());
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CacheError {
+    NotInitialized,
+    // 其他解码错误; try_get 使用 bincode 时会进一步区分出 Truncated 和 TypeMismatch
+    Decode(String),
+    // 数据比预期短 (部分写入、快照损坏等), 适合删除后重新加载
+    Truncated(String),
+    // 数据完整但内容不符合目标类型 (非法的 bool/utf8/枚举值等).
+    // bincode 不带类型信息, 用错类型读取时也可能因为长度不符表现为 Truncated
+    TypeMismatch(String),
+    // get_or_insert_with_retry / get_or_insert_try_with 的 loader 在 loader_failure_ttl 内失败过, 本次没有调用 loader
+    // (get_or_insert_with_exp 同样遵守这段时间);
+    // error 为上次失败的错误信息, retry_after 后才会再次调用
+    LoaderBackoff {
+        error: String,
+        retry_after: Duration,
+    },
+    // insert_all_or_none 整批写入后的权重总和 required 超过容量
+    CapacityExceeded {
+        required: u64,
+        capacity: u64,
+    },
+    // key 的字节数超过 CacheConfig::max_key_len
+    KeyTooLong {
+        len: usize,
+        max: usize,
+    },
+    // insert_with_checksum 写入的数据 CRC32 校验失败 (内存或快照损坏), 数据不可信
+    ChecksumMismatch,
+    // entry 的内容类型与读取方式不符, 例如用 get 读取 insert_json 写入的 entry
+    ContentTypeMismatch(ContentType),
+}
+
+// entry 的内容类型: Encoded 为按 codec 序列化的数据, Json 为 insert_json 原样保存的 JSON 文本,
+// Weak 为 insert_with_weak_reference 保存的弱引用 (没有字节数据)
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ContentType {
+    #[default]
+    Encoded,
+    Json,
+    Weak,
+}
+
+impl std::fmt::Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::NotInitialized => write!(f, "cache is null"),
+            CacheError::Decode(e) => write!(f, "cache deserialize error: {}", e),
+            CacheError::Truncated(e) => write!(f, "cache deserialize error, truncated: {}", e),
+            CacheError::TypeMismatch(e) => {
+                write!(f, "cache deserialize error, type mismatch: {}", e)
+            }
+            CacheError::LoaderBackoff { error, retry_after } => write!(
+                f,
+                "cache loader failed recently: {}, retry after {:?}",
+                error, retry_after
+            ),
+            CacheError::CapacityExceeded { required, capacity } => {
+                write!(f, "cache capacity exceeded: {} > {}", required, capacity)
+            }
+            CacheError::KeyTooLong { len, max } => {
+                write!(f, "cache key too long: {} bytes, max {}", len, max)
+            }
+            CacheError::ChecksumMismatch => write!(f, "cache checksum mismatch"),
+            CacheError::ContentTypeMismatch(t) => {
+                write!(f, "cache entry content type mismatch: {:?}", t)
+            }
         }
-        return None;
     }
-    None
 }
 
-pub fn deserialize<V>(d: &[u8]) -> Option<V>
-where
-    V: DeserializeOwned + Sync + Send,
-{
-    let c = config::standard();
-    let b = bincode::serde::decode_from_slice::<V, _>(d, c);
-    if let Ok((value, _)) = b {
-        return Some(value);
+impl std::error::Error for CacheError {}
+
+// 值的编码方式, 在 CacheConfig/CacheBuilder 中选择, 之后所有 insert/get 都使用它;
+// get_bytes 等原始字节接口不受影响. 切换 codec 后旧格式的数据无法读取
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Codec {
+    #[default]
+    Bincode,
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "msgpack")]
+    Msgpack,
+}
+
+impl Codec {
+    fn encode<V: Serialize + ?Sized>(self, value: &V) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            Codec::Bincode => bincode::serde::encode_to_vec(value, config::standard()),
+            #[cfg(feature = "json")]
+            Codec::Json => {
+                serde_json::to_vec(value).map_err(|e| EncodeError::OtherString(e.to_string()))
+            }
+            #[cfg(feature = "msgpack")]
+            Codec::Msgpack => {
+                rmp_serde::to_vec(value).map_err(|e| EncodeError::OtherString(e.to_string()))
+            }
+        }
     }
-    if let Err(e) = b {
-        log::error!("deserialize error: {}", e.to_string());
+
+    fn decode<V: DeserializeOwned>(self, b: &[u8]) -> Result<V, DecodeError> {
+        match self {
+            Codec::Bincode => {
+                let (value, _) = bincode::serde::decode_from_slice::<V, _>(b, config::standard())?;
+                Ok(value)
+            }
+            #[cfg(feature = "json")]
+            Codec::Json => {
+                serde_json::from_slice(b).map_err(|e| DecodeError::OtherString(e.to_string()))
+            }
+            #[cfg(feature = "msgpack")]
+            Codec::Msgpack => {
+                rmp_serde::from_slice(b).map_err(|e| DecodeError::OtherString(e.to_string()))
+            }
+        }
     }
-    return None;
 }
 
-// pub fn get<K, V>(key: K) -> Option<(Expiration, V)>
-// where
-//     K: Into<String>,
-//     V: DeserializeOwned + Decode<()> + Sync + Send,
-// {
-//     if let Some(h) = CacheHand.get() {
-//         let k = key.into();
+// trace-ops: 每次 get/insert/remove 调用一次 on_op, 用于短时间的线上排查, 不建议常开
+#[cfg(feature = "trace-ops")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OpKind {
+    Get,
+    Insert,
+    Remove,
+}
 
-//         let v = h.get(&k)?;
+#[cfg(feature = "trace-ops")]
+#[derive(Clone, Debug)]
+pub struct OpTrace<'a> {
+    pub op: OpKind,
+    pub key: &'a str,
+    // get/remove 是否命中; insert 和不返回结果的 remove 为 None
+    pub hit: Option<bool>,
+    // 存储的字节数, 未命中为 0
+    pub bytes: usize,
+    // 只包含缓存本身的耗时, 不含序列化
+    pub elapsed: Duration,
+}
 
-//         let c = config::standard();
-//         let b = bincode::decode_from_slice::<V, _>(v.1.as_ref(), c);
-//         if let Ok((value, _)) = b {
-//             return Some((v.0, value));
-//         }
-//         if let Err(e) = b {
-//             log::error!("cache deserialize error: {}", e.to_string());
-//         }
-//         return None;
-//     }
+#[cfg(feature = "trace-ops")]
+pub type OpTraceCallback = Arc<dyn Fn(&OpTrace) + Send + Sync>;
 
-//     None
-// }
+// union 时 key 冲突的处理方式: Left 为 self, Right 为 other
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictResolution {
+    TakeLeft,
+    TakeRight,
+    TakeOldest,
+    TakeNewest,
+}
 
-pub fn get_exp<K>(key: K) -> Option<Expiration>
-where
-    K: Into<String>,
-{
-    let value = CacheHand.get().map(|h| h.get(&key.into())).unwrap_or(None);
-    if let Some(v) = value {
-        return Some(v.0);
+// insert_or_extend_ttl 的结果, Updated 带回被替换的旧字节和旧的过期时间
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InsertResult {
+    Inserted,
+    Updated {
+        old_value: Vec<u8>,
+        old_exp: Expiration,
+    },
+    Extended,
+}
+
+// 由多个部分拼接 key, 用 ':' 分隔, 各部分中的 '\' 和 ':' 前加 '\' 转义,
+// 因此不同的 parts 一定得到不同的 key: ["a:b", "c"] 为 a\:b:c, 与 ["a", "b:c"] 的 a:b\:c 不会冲突
+pub fn cache_key(parts: &[&dyn std::fmt::Display]) -> String {
+    let mut key = String::new();
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            key.push(':');
+        }
+        for c in part.to_string().chars() {
+            if c == '\\' || c == ':' {
+                key.push('\\');
+            }
+            key.push(c);
+        }
     }
-    None
+    key
 }
 
-pub fn remove<K>(key: K)
-where
-    K: Into<String>,
-{
-    let k = key.into();
-    CacheHand.get().map(|h| {
-        h.invalidate(&k);
-    });
+// 同一个缓存中保存多种类型时, 每种类型注册一个唯一的标识, 读取时按标识检查类型
+pub trait CacheTag {
+    const TAG: &'static str;
 }
 
-pub fn contains_key<K>(key: K) -> bool
-where
-    K: Into<String>,
-{
-    let k = key.into();
-    CacheHand.get().map(|h| h.contains_key(&k)).unwrap_or(false)
+// get_tagged 的结果: 类型标识和未解析的字节, downcast 时检查标识
+#[derive(Clone, Debug)]
+pub struct TaggedValue {
+    tag: String,
+    bytes: Vec<u8>,
+    codec: Codec,
 }
 
-//每隔10检查缓存是否过期
-pub fn check_exp_interval() {
-    if let Some(cache) = CacheHand.get() {
-        cache.run_pending_tasks();
+impl TaggedValue {
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn is<V: CacheTag>(&self) -> bool {
+        self.tag == V::TAG
+    }
+
+    // 标识与 V::TAG 不一致返回 None, 一致但解析失败时记录日志并返回 None
+    pub fn downcast<V>(&self) -> Option<V>
+    where
+        V: CacheTag + DeserializeOwned,
+    {
+        if !self.is::<V>() {
+            return None;
+        }
+        match self.codec.decode(&self.bytes) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::error!("tagged value: {} deserialize error: {}", self.tag, e);
+                None
+            }
+        }
     }
 }
 
-// 刷新key ttl
-pub fn refresh<K>(key: K) -> Result<()>
-where
-    K: Into<String>,
-{
-    if let Some(h) = CacheHand.get() {
-        let k = key.into();
-        let v = h.get(&k);
-        let Some(v) = v else {
-            return Err(anyhow!("key: {} not found", k));
+mod canonical;
+mod macros;
+#[cfg(any(test, feature = "testing"))]
+mod testing;
+#[cfg(any(test, feature = "testing"))]
+pub use testing::{assert_contains, assert_empty, assert_entry_eq};
+
+// typed_cache! 等宏内部使用
+#[doc(hidden)]
+pub mod __private {
+    pub use anyhow::Result;
+}
+#[cfg(feature = "tower")]
+mod layer;
+#[cfg(feature = "tower")]
+pub use layer::{CacheLayer, CacheService, KeyExtractor, KeyExtractorFn};
+
+pub struct CacheExpiry;
+
+pub type CacheData = (Expiration, Vec<u8>);
+
+type AppCache = Cache<String, CacheEntry>;
+
+// 内部存储的 entry, 在 CacheData 之外记录写入时间
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    data: CacheData,
+    inserted_at: Instant,
+    // 每次写入分配的版本号, 重新计时 (refresh 等) 时保持不变; on_evict 等按它匹配到具体的 entry
+    version: u64,
+    // insert_bytes_shared 写入的共享 buffer, 此时 data.1 为空
+    #[cfg(feature = "bytes")]
+    shared: Option<Bytes>,
+    // insert_bytes_with_type_tag 写入的类型名
+    type_tag: Option<String>,
+    content_type: ContentType,
+    // insert_with_weak_reference 写入的 Weak<V>
+    weak: Option<Arc<dyn std::any::Any + Send + Sync>>,
+    // insert_with_checksum 写入: 存储的字节前 4 字节为后续字节的 CRC32 (大端)
+    checksum: bool,
+    // insert_ephemeral 写入的 entry 为 false: 只在本进程内有意义, 导出到文件时跳过
+    persist: bool,
+}
+
+static ENTRY_VERSION: AtomicU64 = AtomicU64::new(0);
+
+impl CacheEntry {
+    fn new(exp: Expiration, b: Vec<u8>) -> Self {
+        Self {
+            data: (exp, b),
+            inserted_at: Instant::now(),
+            version: ENTRY_VERSION.fetch_add(1, Ordering::Relaxed),
+            #[cfg(feature = "bytes")]
+            shared: None,
+            type_tag: None,
+            content_type: ContentType::Encoded,
+            weak: None,
+            checksum: false,
+            persist: true,
+        }
+    }
+
+    // 剩余存活时间, Never 返回 None, 已过期返回 Duration::ZERO
+    fn remaining(&self) -> Option<Duration> {
+        let ttl = self.data.0.as_duration()?;
+        Some(ttl.saturating_sub(self.inserted_at.elapsed()))
+    }
+
+    // 以剩余 ttl 作为新的过期时间, 用于保留 ttl 的改写; 已过期返回 None
+    // (不能交给 from_duration, Duration::ZERO 会变成 Never)
+    fn remaining_exp(&self) -> Option<Expiration> {
+        match self.remaining() {
+            None => Some(Expiration::Never),
+            Some(d) if d.is_zero() => None,
+            Some(d) => Some(Expiration::from_duration(d)),
+        }
+    }
+
+    // 存储的字节, 不含 checksum
+    fn bytes(&self) -> &[u8] {
+        let b = self.stored_bytes();
+        if self.checksum {
+            return b.get(4..).unwrap_or_default();
+        }
+        b
+    }
+
+    fn stored_bytes(&self) -> &[u8] {
+        #[cfg(feature = "bytes")]
+        if let Some(b) = &self.shared {
+            return b;
+        }
+        &self.data.1
+    }
+
+    fn checksum_ok(&self) -> bool {
+        if !self.checksum {
+            return true;
+        }
+        let Some((sum, payload)) = self.stored_bytes().split_first_chunk::<4>() else {
+            return false;
         };
+        u32::from_be_bytes(*sum) == crc32(payload)
+    }
 
-        if v.0 == Expiration::Never {
-            return Ok(());
+    fn into_data(self) -> CacheData {
+        if self.checksum {
+            return (self.data.0, self.bytes().to_vec());
+        }
+        #[cfg(feature = "bytes")]
+        if let Some(b) = self.shared {
+            return (self.data.0, b.to_vec());
+        }
+        self.data
+    }
+}
+
+// CRC32 (IEEE), 用于 insert_with_checksum
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(b: &[u8]) -> u32 {
+    let mut c = !0u32;
+    for x in b {
+        c = CRC32_TABLE[((c ^ u32::from(*x)) & 0xFF) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+// 在字节前加上 4 字节的 CRC32 (大端)
+fn with_crc32(b: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(b.len() + 4);
+    data.extend_from_slice(&crc32(b).to_be_bytes());
+    data.extend_from_slice(b);
+    data
+}
+
+// insert_chunked 的清单: (块数, 总长度, CRC32)
+type ChunkManifest = (u32, u64, u32);
+
+// 数据块的 key 加上普通 key 不会使用的前缀, 避免与 key:N 形式的用户 key (如 cache_key 拼出的) 冲突
+const CHUNK_KEY_PREFIX: &str = "\0chunk:";
+
+fn chunk_key(k: &str, i: u32) -> String {
+    format!("{}{}:{}", CHUNK_KEY_PREFIX, k, i)
+}
+
+// 标准 base64 (带 = 补齐), 用于 export_to_json_file
+#[cfg(feature = "json")]
+fn base64_encode(b: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(b.len().div_ceil(3) * 4);
+    for chunk in b.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, x)| n | (u32::from(*x) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(TABLE[(n >> (18 - 6 * i)) as usize & 0x3F]));
+            } else {
+                out.push('=');
+            }
         }
+    }
+    out
+}
+
+struct EntryExpiry;
 
-        h.invalidate(&k);
+impl Expiry<String, CacheEntry> for EntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        value: &CacheEntry,
+        _current_time: Instant,
+    ) -> Option<Duration> {
+        // 新写入的 entry 即完整 ttl; 从其他缓存迁移来的 entry 保留原来的过期时间
+        value.remaining()
+    }
 
-        h.insert(k, v);
+    // 覆盖写入和重新计时都会更新 inserted_at, 过期时间按新 entry 的 inserted_at 计算,
+    // 与 remaining() 保持一致 (moka 默认沿用旧 entry 的过期时间)
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        value: &CacheEntry,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        value.remaining()
+    }
+}
 
-        return Ok(());
+impl Expiry<String, (Expiration, Vec<u8>)> for CacheExpiry {
+    #[allow(unused_variables)]
+    fn expire_after_create(
+        &self,
+        key: &String,
+        value: &(Expiration, Vec<u8>),
+        current_time: Instant,
+    ) -> Option<Duration> {
+        value.0.as_duration()
     }
+}
 
-    Err(anyhow!("cache is null"))
+// 权重函数: (key, value) -> weight
+// 权重上限为 u32::MAX, 超过的 entry 应饱和为 u32::MAX;
+// 单个 entry 的权重大于 max_capacity 时, moka 会在下一次维护时直接将其驱逐
+pub type Weigher = Arc<dyn Fn(&str, &CacheData) -> u32 + Send + Sync>;
+
+// 默认权重: value 的字节数
+pub fn value_bytes_weigher(_key: &str, value: &CacheData) -> u32 {
+    u32::try_from(value.1.len()).unwrap_or(u32::MAX)
 }
 
-#[cfg(test)]
-#[allow(dead_code)]
-mod test {
+pub type MissCallback = Arc<dyn Fn(&str) + Send + Sync>;
 
-    use super::*;
-    use std::thread::sleep;
+pub type SoftThresholdCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
 
-    fn cache_key_expired(key: Arc<String>, value: CacheData, cause: RemovalCause) {
-        println!("过期 key-----> {key}. value--> {value:?}. Cause: {cause:?}");
+#[derive(Clone)]
+pub struct CacheConfig {
+    // weigher 为 None 时按 entry 个数计算容量, 否则按权重总和计算
+    pub max_capacity: u64,
+    pub callback: Option<fn(Arc<String>, CacheData, RemovalCause)>,
+    pub weigher: Option<Weigher>,
+    // 每 N 次 get/insert 顺带执行一次 run_pending_tasks, 0 表示关闭;
+    // 触发维护的那次调用会多出一次维护耗时 (过期清理和回调通知都在当前线程执行)
+    pub opportunistic_maintenance: u32,
+    // 同一个 key 两次写入的最小间隔, 间隔内的写入被丢弃, 用于避免频繁覆盖导致 ttl 不断重置和 Replaced 回调泛滥.
+    // 对 insert/insert_if_absent/insert_indexed/insert_bytes 等逐个 key 的写入接口生效,
+    // 上次写入时间单独记录, refresh/reset 类操作不算写入; bulk_load 等批量接口和 rekey_all 等迁移不受限制.
+    // try_insert/insert_if_absent/try_insert_without_evicting 被丢弃时返回 false,
+    // insert 等返回 Result<()> 或无返回值的接口被丢弃时照常返回 Ok(()), 需要知道结果时用 try_insert
+    pub min_write_interval: Option<Duration>,
+    // 为 false 时 RemovalCause::Replaced 不再调用 callback, 默认 true;
+    // 被覆盖的旧值不会再通知, 需要清理的资源要在覆盖写入的地方自行处理
+    pub notify_on_replace: bool,
+    // 容量预警: weighted_size 达到 max_capacity * 比例时调用 on_soft_threshold(当前大小, 容量);
+    // 触发后需回落到阈值的 90% 以下才会再次触发. weighted_size 在维护任务执行后才更新
+    pub soft_capacity_threshold: Option<f64>,
+    pub on_soft_threshold: Option<SoftThresholdCallback>,
+    // 存储的字节前加 4 字节大端长度前缀, get/get_bytes 读取时去掉, get_framed 原样返回
+    pub length_prefix: bool,
+    // 确定性编码: 编码前按 key 的编码字节对所有 map 排序, 相等的值总是得到相同的字节, 可按字节比较.
+    // 不开启时 HashMap 的编码顺序与遍历顺序一致, 相等的 HashMap 可能编码不同;
+    // 基本类型、String、Vec、Option、struct、enum、BTreeMap/BTreeSet 无论是否开启都是确定的.
+    // HashSet 按序列编码, 无法排序, 需要字节比较时请换成 BTreeSet. 开启后编码会多一次中间拷贝
+    pub deterministic_encoding: bool,
+    // 严格过期: 读取时再按写入时间检查一次 ttl, 已到期即视为未命中 (不依赖 moka 的清理进度);
+    // 不主动 invalidate, 让 moka 之后以 RemovalCause::Expired 清理, 回调的原因保持准确
+    pub strict_expiry: bool,
+    pub codec: Codec,
+    #[cfg(feature = "trace-ops")]
+    pub on_op: Option<OpTraceCallback>,
+    // 写入时把字节保存为共享的 Bytes: 读取时不再复制整块 value (moka 每次 get 都会 clone 一份 entry),
+    // get_bytes_shared 返回同一块内存的引用, get_bytes 仍然返回一份拷贝.
+    // 共享的 buffer 是只读的, 需要修改时用 get_bytes 取得自己的拷贝. 适合大而只读的 value
+    #[cfg(feature = "bytes")]
+    pub share_bytes: bool,
+    // entry 个数上限, 与按字节的 max_capacity 同时生效: 每个 entry 的权重至少为 max_capacity / max_entries,
+    // 因此 weighted_size 会大于实际字节数. 容量驱逐按当时的约束分别计入
+    // CacheStats::size_evictions_by_entries / size_evictions_by_bytes
+    pub max_entries: Option<u64>,
+    // get/try_get 未命中时按注册顺序调用, 参数为 key; 在调用 get 的线程中同步执行, 不要做耗时操作
+    pub on_miss: Vec<MissCallback>,
+    // 对应 moka 的 eviction_policy. 默认 TinyLFU: 新 key 需要在频率估计 (Count-Min Sketch) 上
+    // 高于被淘汰的候选者才能写入, 只访问一次的扫描流量很难挤掉热点数据, 即抗扫描;
+    // lru() 关闭准入过滤, 总是淘汰最久未访问的 entry, 适合访问有时效性、新数据总是更热的场景.
+    // moka 不支持配置准入所需的最小频率, 也没有 protected/probationary 分段的比例参数
+    pub eviction_policy: EvictionPolicy,
+    // 记录每个 key 的命中次数, 用于 telemetry_report 的 top_10_keys_by_access;
+    // 每次命中都要加锁更新计数, 默认关闭
+    pub track_access_counts: bool,
+    // insert_with_checksum 在数据前加 4 字节 CRC32, 读取时校验; 关闭时 insert_with_checksum 等同 insert
+    pub checksums: bool,
+    // key 的最大字节数, 超出时 insert/try_insert/insert_if_absent/insert_json 等返回 Result 的写入接口
+    // 返回 CacheError::KeyTooLong (可通过 anyhow 的 downcast_ref 取得). 所有写入都会检查:
+    // insert_bytes、bulk_load、copy_all_to、union 等不返回错误的接口记录日志后跳过超长的 key,
+    // insert_chunked 的数据块 key 同样不能超出, get_or_parse 超出时只解析不缓存. 默认不限制
+    pub max_key_len: Option<usize>,
+    // access-log: 最近 access_log_capacity 次读取的 (key, 时间), 包括未命中, 通过 recent_accesses 查询;
+    // 每次读取都要加锁写入, 只用于排查访问分布和验证 TTL 的选择. 0 为关闭
+    #[cfg(feature = "access-log")]
+    pub access_log_capacity: usize,
+    // get_or_insert_with_retry 的 loader 全部重试失败 (或 get_or_insert_try_with 的 loader 失败) 后, 同一 key 在这段时间内直接返回
+    // CacheError::LoaderBackoff 而不再调用 loader (失败结果的短时缓存), 避免后端故障时每个调用都去重试.
+    // 默认 None, 每次未命中都调用 loader
+    pub loader_failure_ttl: Option<Duration>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self::new(32 * 1024 * 1024)
     }
-    fn init() {
-        setup(Some(cache_key_expired), 512).unwrap();
+}
+
+impl CacheConfig {
+    // 默认按 value 字节数计算权重, max_capacity 单位为字节
+    pub fn new(max_capacity: u64) -> Self {
+        Self {
+            max_capacity,
+            callback: None,
+            weigher: Some(Arc::new(value_bytes_weigher)),
+            opportunistic_maintenance: 0,
+            min_write_interval: None,
+            notify_on_replace: true,
+            soft_capacity_threshold: None,
+            on_soft_threshold: None,
+            length_prefix: false,
+            deterministic_encoding: false,
+            strict_expiry: false,
+            codec: Codec::Bincode,
+            #[cfg(feature = "trace-ops")]
+            on_op: None,
+            #[cfg(feature = "bytes")]
+            share_bytes: false,
+            max_entries: None,
+            on_miss: Vec::new(),
+            eviction_policy: EvictionPolicy::default(),
+            track_access_counts: false,
+            checksums: false,
+            max_key_len: None,
+            #[cfg(feature = "access-log")]
+            access_log_capacity: 0,
+            loader_failure_ttl: None,
+        }
     }
 
-    #[test]
-    fn test_encode_decode() {
+    // max_entries 对应的最小权重
+    fn entry_weight_floor(&self) -> Option<u32> {
+        let n = self.max_entries.filter(|n| *n > 0)?;
+        Some(u32::try_from(self.max_capacity.div_ceil(n)).unwrap_or(u32::MAX))
+    }
+
+    // 自定义权重, 例如: key.len() + value.1.len() + 固定开销
+    pub fn with_weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&str, &CacheData) -> u32 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Arc::new(weigher));
+        self
+    }
+}
+
+// CacheConfig 的链式写法, 未提供的选项直接修改 CacheConfig 后用 From 转换
+#[derive(Clone, Default)]
+pub struct CacheBuilder {
+    config: CacheConfig,
+}
+
+impl From<CacheConfig> for CacheBuilder {
+    fn from(config: CacheConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl CacheBuilder {
+    pub fn new(max_capacity: u64) -> Self {
+        CacheConfig::new(max_capacity).into()
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.config.codec = codec;
+        self
+    }
+
+    pub fn callback(mut self, callback: fn(Arc<String>, CacheData, RemovalCause)) -> Self {
+        self.config.callback = Some(callback);
+        self
+    }
+
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&str, &CacheData) -> u32 + Send + Sync + 'static,
+    {
+        self.config = self.config.with_weigher(weigher);
+        self
+    }
+
+    // 可注册多个, 按注册顺序调用
+    pub fn on_miss<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.config.on_miss.push(Arc::new(f));
+        self
+    }
+
+    pub fn eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.config.eviction_policy = policy;
+        self
+    }
+
+    pub fn max_entries(mut self, max_entries: u64) -> Self {
+        self.config.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn max_key_len(mut self, max_key_len: usize) -> Self {
+        self.config.max_key_len = Some(max_key_len);
+        self
+    }
+
+    #[cfg(feature = "access-log")]
+    pub fn access_log_capacity(mut self, capacity: usize) -> Self {
+        self.config.access_log_capacity = capacity;
+        self
+    }
+
+    pub fn loader_failure_ttl(mut self, ttl: Duration) -> Self {
+        self.config.loader_failure_ttl = Some(ttl);
+        self
+    }
+
+    pub fn enable_checksums(mut self) -> Self {
+        self.config.checksums = true;
+        self
+    }
+
+    // 注册 Ctrl-C (SIGINT) 处理: 依次执行 shutdown() 和 invalidate_all(), 让积压的过期通知以及
+    // 所有剩余 entry (RemovalCause::Explicit) 的 callback 在退出前发出, 然后以 130 退出进程.
+    // 注册后 Ctrl-C 不再直接终止进程, 而是由这里处理完再退出; 作用于全局缓存, build() 创建的实例不受影响.
+    // ctrlc 每个进程只能注册一次, 重复注册返回 Err
+    #[cfg(feature = "ctrlc")]
+    pub fn register_ctrl_c_handler(self) -> Result<Self> {
+        ctrlc::set_handler(|| {
+            log::info!("ctrl-c received, flushing cache");
+            shutdown();
+            invalidate_all();
+            std::process::exit(130);
+        })
+        .map_err(|e| anyhow!("register ctrl-c handler error: {}", e))?;
+        Ok(self)
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn build(self) -> CacheClient {
+        CacheClient::new(self.config)
+    }
+
+    // 初始化全局缓存
+    pub fn setup(self) -> Result<()> {
+        setup_with_config(self.config)
+    }
+}
+
+// 二级索引: 索引值 -> 主 key, 随主 key 的 entry 一起失效
+#[derive(Default)]
+struct SecondaryIndex {
+    keys: HashMap<String, String>,
+    // 主 key -> (entry 版本, 索引值), 版本用来区分同一个 key 的不同写入, 重新计时后不变
+    owners: HashMap<String, (u64, Vec<String>)>,
+}
+
+impl SecondaryIndex {
+    fn remove_owner(&mut self, key: &str) {
+        let Some((_, indexes)) = self.owners.remove(key) else {
+            return;
+        };
+        for i in indexes {
+            if self.keys.get(&i).is_some_and(|k| k == key) {
+                self.keys.remove(&i);
+            }
+        }
+    }
+}
+
+// 统计计数, 通过 CacheClient::stats() 读取
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+    evictions: AtomicU64,
+    // 容量驱逐 (RemovalCause::Size) 按驱逐时的约束区分: entry 个数超限 / 字节数超限
+    size_evictions_by_entries: AtomicU64,
+    size_evictions_by_bytes: AtomicU64,
+    // 按 RemovalCause 分别计数: Expired, Explicit, Replaced, Size
+    removals: [AtomicU64; 4],
+    // 被删除 entry 的存活时间分布, 每个桶的上限见 LIFETIME_BUCKETS
+    lifetimes: [AtomicU64; LIFETIME_BUCKETS.len()],
+    // 过期通知的最大延迟 (微秒): 从 entry 到期到 eviction listener 被调用
+    max_expire_notify_delay_us: AtomicU64,
+    // 按 codec 成功序列化/反序列化的次数和字节数 (含 length_prefix), 不含 insert_bytes 等原始字节接口
+    encodes: AtomicU64,
+    bytes_encoded: AtomicU64,
+    decodes: AtomicU64,
+    bytes_decoded: AtomicU64,
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn inserts(&self) -> u64 {
+        self.inserts.load(Ordering::Relaxed)
+    }
+
+    // 过期和容量驱逐的个数, 不包括删除和覆盖
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    // 没有 weigher 时容量就是 entry 个数, 全部计入这里; 同时设置 weigher 和 max_entries 时,
+    // 驱逐时实际字节数未超过 max_capacity 的计入这里
+    pub fn size_evictions_by_entries(&self) -> u64 {
+        self.size_evictions_by_entries.load(Ordering::Relaxed)
+    }
+
+    pub fn size_evictions_by_bytes(&self) -> u64 {
+        self.size_evictions_by_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn removals(&self, cause: RemovalCause) -> u64 {
+        self.removals[cause_index(cause)].load(Ordering::Relaxed)
+    }
+
+    // (桶的上限, 存活时间不超过该上限的删除次数), 最后一个桶的上限为 Duration::MAX
+    pub fn lifetime_histogram(&self) -> Vec<(Duration, u64)> {
+        LIFETIME_BUCKETS
+            .iter()
+            .zip(self.lifetimes.iter())
+            .map(|(bound, n)| (*bound, n.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    // 过期 entry 从到期到收到通知的最大延迟, 偏大说明维护任务执行得不够频繁
+    // (只统计 RemovalCause::Expired, 容量驱逐等没有确定的触发时间)
+    pub fn max_expire_notify_delay(&self) -> Duration {
+        Duration::from_micros(self.max_expire_notify_delay_us.load(Ordering::Relaxed))
+    }
+
+    pub fn encodes(&self) -> u64 {
+        self.encodes.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_encoded(&self) -> u64 {
+        self.bytes_encoded.load(Ordering::Relaxed)
+    }
+
+    pub fn decodes(&self) -> u64 {
+        self.decodes.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_decoded(&self) -> u64 {
+        self.bytes_decoded.load(Ordering::Relaxed)
+    }
+
+    // 计数清零, 用于按时间段上报
+    pub fn reset(&self) {
+        self.snapshot_and_reset();
+    }
+
+    // 读取并清零, 返回清零前的计数; 每个计数单独 swap, 期间并发的操作会计入本次或下一次, 不会丢失
+    pub fn snapshot_and_reset(&self) -> CacheStats {
+        CacheStats {
+            hits: AtomicU64::new(self.hits.swap(0, Ordering::SeqCst)),
+            misses: AtomicU64::new(self.misses.swap(0, Ordering::SeqCst)),
+            inserts: AtomicU64::new(self.inserts.swap(0, Ordering::SeqCst)),
+            evictions: AtomicU64::new(self.evictions.swap(0, Ordering::SeqCst)),
+            size_evictions_by_entries: AtomicU64::new(
+                self.size_evictions_by_entries.swap(0, Ordering::SeqCst),
+            ),
+            size_evictions_by_bytes: AtomicU64::new(
+                self.size_evictions_by_bytes.swap(0, Ordering::SeqCst),
+            ),
+            removals: std::array::from_fn(|i| {
+                AtomicU64::new(self.removals[i].swap(0, Ordering::SeqCst))
+            }),
+            lifetimes: std::array::from_fn(|i| {
+                AtomicU64::new(self.lifetimes[i].swap(0, Ordering::SeqCst))
+            }),
+            max_expire_notify_delay_us: AtomicU64::new(
+                self.max_expire_notify_delay_us.swap(0, Ordering::SeqCst),
+            ),
+            encodes: AtomicU64::new(self.encodes.swap(0, Ordering::SeqCst)),
+            bytes_encoded: AtomicU64::new(self.bytes_encoded.swap(0, Ordering::SeqCst)),
+            decodes: AtomicU64::new(self.decodes.swap(0, Ordering::SeqCst)),
+            bytes_decoded: AtomicU64::new(self.bytes_decoded.swap(0, Ordering::SeqCst)),
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        let c = if hit { &self.hits } else { &self.misses };
+        c.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+const LIFETIME_BUCKETS: [Duration; 7] = [
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(10 * 60),
+    Duration::from_secs(60 * 60),
+    Duration::from_secs(24 * 60 * 60),
+    Duration::MAX,
+];
+
+fn cause_index(cause: RemovalCause) -> usize {
+    match cause {
+        RemovalCause::Expired => 0,
+        RemovalCause::Explicit => 1,
+        RemovalCause::Replaced => 2,
+        RemovalCause::Size => 3,
+    }
+}
+
+// 缓存状态的汇总, 由 telemetry_report 生成, 可以序列化后输出到监控接口
+#[derive(Clone, Debug, Serialize)]
+pub struct TelemetryReport {
+    pub hits: u64,
+    pub misses: u64,
+    pub inserts: u64,
+    // key 为 RemovalCause 的名字 (Expired/Explicit/Replaced/Size), RemovalCause 本身不支持 Hash 和 Serialize
+    pub evictions_by_cause: HashMap<String, u64>,
+    pub entry_count: u64,
+    pub capacity: u64,
+    // key 和 value 的字节数之和, 不含 moka 自身的开销
+    pub estimated_size_bytes: u64,
+    // estimated_memory_bytes 按每个 entry 额外估算的开销
+    pub overhead_per_entry: u64,
+    pub lifetime_histogram: Vec<(Duration, u64)>,
+    // 需要开启 CacheConfig::track_access_counts, 否则为空
+    pub top_10_keys_by_access: Vec<(String, u64)>,
+}
+
+type OnEvict = Box<dyn FnOnce(RemovalCause) + Send>;
+
+// 与 eviction listener 共享的状态
+#[derive(Default)]
+struct ListenerState {
+    index: Mutex<SecondaryIndex>,
+    // insert_with_on_evict 注册的回调: key -> [(entry 版本, 回调)], 按版本匹配到具体的 entry
+    on_evict: Mutex<HashMap<String, Vec<(u64, OnEvict)>>>,
+    stats: CacheStats,
+    // 同时设置 weigher 和 max_entries 时, 按 weigher 计算的实际权重总和 (不含最小权重的补足部分)
+    raw_weight: AtomicU64,
+    // track_access_counts 开启时记录每个 key 的命中次数, entry 删除时一并删除
+    track_access: bool,
+    access_counts: Mutex<HashMap<String, u64>>,
+    // mark_refreshing 标记的 entry: key -> entry 版本, entry 被替换或删除后标记失效
+    refreshing: Mutex<HashMap<String, u64>>,
+    // reset_exp 原地替换、rekey_all 迁移走的 entry 版本, eviction listener 跳过这一次通知
+    silent: Mutex<std::collections::HashSet<u64>>,
+    #[cfg(feature = "async")]
+    events: Option<EventChannel>,
+}
+
+// 淘汰事件: (key, value, 原因)
+#[cfg(feature = "async")]
+pub type EvictionEvent = (String, CacheData, RemovalCause);
+
+// 淘汰事件 channel 已满时的处理方式
+#[cfg(feature = "async")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChannelFullPolicy {
+    // 丢弃当前事件并计数, 不阻塞缓存
+    DropNewest,
+    // 阻塞触发淘汰的线程直到 channel 有空位;
+    // 在 tokio runtime 线程中无法阻塞, 退化为 DropNewest
+    Block,
+}
+
+#[cfg(feature = "async")]
+struct EventChannel {
+    tx: tokio::sync::mpsc::Sender<EvictionEvent>,
+    policy: ChannelFullPolicy,
+    dropped: AtomicU64,
+}
+
+#[cfg(feature = "async")]
+impl EventChannel {
+    fn send(&self, event: EvictionEvent) {
+        use tokio::sync::mpsc::error::TrySendError;
+        let event = match self.tx.try_send(event) {
+            Ok(()) | Err(TrySendError::Closed(_)) => return,
+            Err(TrySendError::Full(event)) => event,
+        };
+        if self.policy == ChannelFullPolicy::Block && tokio::runtime::Handle::try_current().is_err()
+        {
+            // receiver 已关闭时直接丢弃, 不计数
+            let _ = self.tx.blocking_send(event);
+            return;
+        }
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl ListenerState {
+    fn on_removal(&self, key: &str, value: &CacheEntry, cause: RemovalCause) {
+        if cause.was_evicted() {
+            self.stats.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        self.stats.removals[cause_index(cause)].fetch_add(1, Ordering::Relaxed);
+        let lifetime = value.inserted_at.elapsed();
+        let bucket = LIFETIME_BUCKETS
+            .iter()
+            .position(|bound| lifetime <= *bound)
+            .unwrap_or(LIFETIME_BUCKETS.len() - 1);
+        self.stats.lifetimes[bucket].fetch_add(1, Ordering::Relaxed);
+        if self.track_access && cause != RemovalCause::Replaced {
+            self.access_counts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(key);
+        }
+        if cause == RemovalCause::Expired {
+            if let Some(ttl) = value.data.0.as_duration() {
+                let delay = value.inserted_at.elapsed().saturating_sub(ttl);
+                let us = u64::try_from(delay.as_micros()).unwrap_or(u64::MAX);
+                self.stats
+                    .max_expire_notify_delay_us
+                    .fetch_max(us, Ordering::Relaxed);
+            }
+        }
+        let mut refreshing = self.refreshing.lock().unwrap_or_else(|e| e.into_inner());
+        if refreshing.get(key) == Some(&value.version) {
+            refreshing.remove(key);
+        }
+        drop(refreshing);
+
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        if index
+            .owners
+            .get(key)
+            .is_some_and(|(version, _)| *version == value.version)
+        {
+            index.remove_owner(key);
+        }
+        drop(index);
+
+        let mut on_evict = self.on_evict.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(callbacks) = on_evict.get_mut(key) else {
+            return;
+        };
+        let Some(pos) = callbacks
+            .iter()
+            .position(|(version, _)| *version == value.version)
+        else {
+            return;
+        };
+        let (_, f) = callbacks.swap_remove(pos);
+        if callbacks.is_empty() {
+            on_evict.remove(key);
+        }
+        drop(on_evict);
+        f(cause);
+    }
+
+    // 取出 key 上属于该版本 entry 的注册 (on_evict 回调、索引、刷新标记、命中次数), 用于迁移到新 key 或新缓存
+    fn detach(&self, key: &str, version: u64) -> Registrations {
+        let mut on_evict = self.on_evict.lock().unwrap_or_else(|e| e.into_inner());
+        let mut callbacks = Vec::new();
+        if let Some(all) = on_evict.get_mut(key) {
+            let (mine, rest) = std::mem::take(all)
+                .into_iter()
+                .partition(|(v, _)| *v == version);
+            callbacks = mine;
+            *all = rest;
+            if all.is_empty() {
+                on_evict.remove(key);
+            }
+        }
+        drop(on_evict);
+
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        let indexes = match index.owners.get(key) {
+            Some((v, indexes)) if *v == version => indexes.clone(),
+            _ => Vec::new(),
+        };
+        if !indexes.is_empty() {
+            index.remove_owner(key);
+        }
+        drop(index);
+
+        let mut refreshing = self.refreshing.lock().unwrap_or_else(|e| e.into_inner());
+        let refresh = refreshing.get(key) == Some(&version);
+        if refresh {
+            refreshing.remove(key);
+        }
+        drop(refreshing);
+
+        let access = self
+            .access_counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        Registrations {
+            version,
+            callbacks,
+            indexes,
+            refresh,
+            access,
+        }
+    }
+
+    // 把 detach 取出的注册挂到 key 上, key 原有 entry 的注册此时已随覆盖通知清理
+    fn attach(&self, key: &str, r: Registrations) {
+        if !r.callbacks.is_empty() {
+            self.on_evict
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(key.to_string())
+                .or_default()
+                .extend(r.callbacks);
+        }
+        if !r.indexes.is_empty() {
+            let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+            index.remove_owner(key);
+            for i in &r.indexes {
+                index.keys.insert(i.clone(), key.to_string());
+            }
+            index.owners.insert(key.to_string(), (r.version, r.indexes));
+        }
+        if r.refresh {
+            self.refreshing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.to_string(), r.version);
+        }
+        if let Some(n) = r.access {
+            self.access_counts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.to_string(), n);
+        }
+    }
+}
+
+// rekey_all、partition_by 等随 entry 迁移的注册
+struct Registrations {
+    version: u64,
+    callbacks: Vec<(u64, OnEvict)>,
+    indexes: Vec<String>,
+    refresh: bool,
+    access: Option<u64>,
+}
+
+fn build_cache(config: CacheConfig, state: Arc<ListenerState>) -> AppCache {
+    let mut c = Cache::builder()
+        .max_capacity(config.max_capacity)
+        .eviction_policy(config.eviction_policy.clone())
+        .expire_after(EntryExpiry);
+
+    let callback = config.callback;
+    let notify_on_replace = config.notify_on_replace;
+    let weigher = config.weigher.clone();
+    let track_raw = weigher.is_some() && config.max_entries.is_some();
+    let max_capacity = config.max_capacity;
+    let listener_state = state.clone();
+    c = c.eviction_listener(move |k, v: CacheEntry, cause| {
+        let state = &listener_state;
+        let raw = u64::from(raw_weight(weigher.as_ref(), &k, &v));
+        if cause == RemovalCause::Size {
+            let by_bytes = match &weigher {
+                None => false,
+                Some(_) if !track_raw => true,
+                Some(_) => state.raw_weight.load(Ordering::Relaxed) > max_capacity,
+            };
+            let c = if by_bytes {
+                &state.stats.size_evictions_by_bytes
+            } else {
+                &state.stats.size_evictions_by_entries
+            };
+            c.fetch_add(1, Ordering::Relaxed);
+        }
+        if track_raw {
+            let _ = state
+                .raw_weight
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |w| {
+                    Some(w.saturating_sub(raw))
+                });
+        }
+        // 重新计时和迁移 key 不是删除, 不通知
+        if matches!(cause, RemovalCause::Replaced | RemovalCause::Explicit)
+            && state
+                .silent
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&v.version)
+        {
+            return;
+        }
+        state.on_removal(&k, &v, cause);
+        if !notify_on_replace && cause == RemovalCause::Replaced {
+            return;
+        }
+        #[cfg(feature = "async")]
+        if let Some(events) = &state.events {
+            events.send((k.as_ref().clone(), v.clone().into_data(), cause));
+        }
+        if let Some(callback) = callback {
+            callback(k, v.into_data(), cause)
+        }
+    });
+    if config.weigher.is_some() || config.max_entries.is_some() {
+        c = c.weigher(move |k: &String, v: &CacheEntry| {
+            if track_raw {
+                let raw = u64::from(raw_weight(config.weigher.as_ref(), k, v));
+                state.raw_weight.fetch_add(raw, Ordering::Relaxed);
+            }
+            entry_weight(&config, k, v)
+        });
+    }
+    c.build()
+}
+
+// moka 使用的权重: weigher 的结果, 不低于 max_entries 对应的最小权重
+fn entry_weight(config: &CacheConfig, k: &str, v: &CacheEntry) -> u32 {
+    let w = raw_weight(config.weigher.as_ref(), k, v);
+    match config.entry_weight_floor() {
+        Some(floor) => w.max(floor),
+        None => w,
+    }
+}
+
+// 没有 weigher 时每个 entry 的权重为 1
+fn raw_weight(weigher: Option<&Weigher>, k: &str, v: &CacheEntry) -> u32 {
+    let Some(weigher) = weigher else {
+        return 1;
+    };
+    let w = weigher(k, &v.data);
+    // 共享 buffer 不在 data.1 中, 额外按字节数计入权重
+    #[cfg(feature = "bytes")]
+    let w = w.saturating_add(
+        v.shared
+            .as_ref()
+            .map_or(0, |b| u32::try_from(b.len()).unwrap_or(u32::MAX)),
+    );
+    w
+}
+
+// 独立的缓存实例, 全局函数都委托给 setup 创建的实例
+// 可 clone 的句柄, 所有 clone 共享同一个 InnerCache (同一个缓存、统计和配置)
+#[derive(Clone)]
+pub struct CacheClient {
+    inner: Arc<InnerCache>,
+}
+
+struct InnerCache {
+    // set_max_capacity 时整体替换为按新容量重建的缓存
+    cache: RwLock<AppCache>,
+    config: CacheConfig,
+    ops: AtomicU32,
+    state: Arc<ListenerState>,
+    soft_fired: AtomicBool,
+    // bulk_load 期间持有写锁, 读操作持有读锁
+    load_gate: RwLock<()>,
+    // 按 key 哈希分段的锁, 用于 读取-改写 类操作
+    key_locks: [Mutex<()>; KEY_LOCK_STRIPES],
+    // freeze 的调用线程; 冻结期间其他线程经过 lookup 的读取等待 thaw
+    frozen: AtomicBool,
+    frozen_by: Mutex<Option<std::thread::ThreadId>>,
+    thawed: std::sync::Condvar,
+    #[cfg(feature = "access-log")]
+    access_log: Mutex<std::collections::VecDeque<(String, Instant)>>,
+    // loader_failure_ttl 开启时记录 loader 最近一次失败: key -> (失败时间, 错误信息)
+    loader_failures: Mutex<HashMap<String, (Instant, String)>>,
+    // min_write_interval 开启时记录最近写入过的 key, 按间隔自动过期
+    recent_writes: Option<Cache<String, ()>>,
+}
+
+const KEY_LOCK_STRIPES: usize = 64;
+// estimated_memory_bytes 按每个 entry 估算的结构开销 (CacheEntry、key 的 String 等)
+const ENTRY_OVERHEAD_BYTES: usize = 48;
+const CONSISTENT_READ_RETRIES: usize = 3;
+// 冻结期间读取最多等待的时间, 超时后照常读取, 避免忘记 thaw 时读取永久阻塞
+const FREEZE_MAX_WAIT: Duration = Duration::from_secs(5);
+
+impl CacheClient {
+    pub fn new(config: CacheConfig) -> Self {
+        Self::with_state(config, ListenerState::default())
+    }
+
+    // 淘汰事件同时发送到容量为 cap 的 channel, channel 满时按 policy 处理
+    #[cfg(feature = "async")]
+    pub fn with_bounded_channel(
+        config: CacheConfig,
+        cap: usize,
+        policy: ChannelFullPolicy,
+    ) -> (Self, tokio::sync::mpsc::Receiver<EvictionEvent>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(cap);
+        let state = ListenerState {
+            events: Some(EventChannel {
+                tx,
+                policy,
+                dropped: AtomicU64::new(0),
+            }),
+            ..Default::default()
+        };
+        (Self::with_state(config, state), rx)
+    }
+
+    // channel 已满被丢弃的淘汰事件个数
+    #[cfg(feature = "async")]
+    pub fn dropped_events(&self) -> u64 {
+        self.inner
+            .state
+            .events
+            .as_ref()
+            .map_or(0, |e| e.dropped.load(Ordering::Relaxed))
+    }
+
+    // 已发送到 channel 但接收端还未取走的淘汰事件个数, 持续增长说明接收端处理不过来
+    #[cfg(feature = "async")]
+    pub fn pending_events(&self) -> usize {
+        self.inner
+            .state
+            .events
+            .as_ref()
+            .map_or(0, |e| e.tx.max_capacity() - e.tx.capacity())
+    }
+
+    fn with_state(config: CacheConfig, mut state: ListenerState) -> Self {
+        state.track_access = config.track_access_counts;
+        let state = Arc::new(state);
+        let recent_writes = config
+            .min_write_interval
+            .map(|interval| Cache::builder().time_to_live(interval).build());
+        let inner = InnerCache {
+            cache: RwLock::new(build_cache(config.clone(), state.clone())),
+            config,
+            ops: AtomicU32::new(0),
+            state,
+            soft_fired: AtomicBool::new(false),
+            load_gate: RwLock::new(()),
+            key_locks: std::array::from_fn(|_| Mutex::new(())),
+            frozen: AtomicBool::new(false),
+            frozen_by: Mutex::new(None),
+            thawed: std::sync::Condvar::new(),
+            #[cfg(feature = "access-log")]
+            access_log: Mutex::new(std::collections::VecDeque::new()),
+            loader_failures: Mutex::new(HashMap::new()),
+            recent_writes,
+        };
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    // 当前的 moka 缓存; 克隆只增加引用计数, 不持有锁, 避免回调中再次访问时死锁
+    fn cache(&self) -> AppCache {
+        self.inner
+            .cache
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // 读操作统一经过这里, bulk_load 进行中会等待其完成
+    fn lookup(&self, k: &String) -> Option<CacheEntry> {
+        #[cfg(feature = "trace-ops")]
+        let start = Instant::now();
+        self.wait_thaw();
+        let _gate = self
+            .inner
+            .load_gate
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        let v = self.cache().get(k).filter(|v| {
+            !(self.inner.config.strict_expiry && v.remaining() == Some(Duration::ZERO))
+        });
+        self.inner.state.stats.record(v.is_some());
+        if self.inner.state.track_access && v.is_some() {
+            *self
+                .inner
+                .state
+                .access_counts
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .entry(k.clone())
+                .or_default() += 1;
+        }
+        #[cfg(feature = "access-log")]
+        self.record_access(k);
+        #[cfg(feature = "trace-ops")]
+        self.trace(OpKind::Get, k, Some(v.is_some()), v.as_ref(), start);
+        v
+    }
+
+    #[cfg(feature = "access-log")]
+    fn record_access(&self, k: &str) {
+        let cap = self.inner.config.access_log_capacity;
+        if cap == 0 {
+            return;
+        }
+        let mut log = self
+            .inner
+            .access_log
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if log.len() >= cap {
+            log.pop_front();
+        }
+        log.push_back((k.to_string(), Instant::now()));
+    }
+
+    // 最近 n 次读取, 最新的在前; 未开启 access_log_capacity 时为空
+    #[cfg(feature = "access-log")]
+    pub fn recent_accesses(&self, n: usize) -> Vec<(String, Instant)> {
+        let log = self
+            .inner
+            .access_log
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        log.iter().rev().take(n).cloned().collect()
+    }
+
+    fn wait_thaw(&self) {
+        if !self.inner.frozen.load(Ordering::Acquire) {
+            return;
+        }
+        let me = std::thread::current().id();
+        let by = self
+            .inner
+            .frozen_by
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if *by == Some(me) {
+            return;
+        }
+        let (by, timeout) = self
+            .inner
+            .thawed
+            .wait_timeout_while(by, FREEZE_MAX_WAIT, |by| by.is_some())
+            .unwrap_or_else(|e| e.into_inner());
+        drop(by);
+        if timeout.timed_out() {
+            log::warn!(
+                "cache still frozen after {:?}, read anyway",
+                FREEZE_MAX_WAIT
+            );
+        }
+    }
+
+    // 写操作统一经过这里, 计入 inserts; 超过 max_key_len 的 key 不写入
+    fn put(&self, k: String, entry: CacheEntry) -> Result<(), CacheError> {
+        self.check_key_len(&k)?;
+        self.inner
+            .state
+            .stats
+            .inserts
+            .fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "trace-ops")]
+        if self.inner.config.on_op.is_some() {
+            let start = Instant::now();
+            let bytes = entry.bytes().len();
+            self.cache().insert(k.clone(), entry);
+            self.trace_bytes(OpKind::Insert, &k, None, bytes, start);
+            return Ok(());
+        }
+        self.cache().insert(k, entry);
+        Ok(())
+    }
+
+    // 对外的逐个 key 写入接口经过这里: 开启 min_write_interval 时在 moka 的 entry 锁内检查并记录写入,
+    // 距离上次写入不足间隔时丢弃, 返回 false
+    fn write(&self, k: String, entry: CacheEntry) -> Result<bool, CacheError> {
+        let Some(recent) = &self.inner.recent_writes else {
+            self.put(k, entry)?;
+            return Ok(true);
+        };
+        self.check_key_len(&k)?;
+        #[cfg(feature = "trace-ops")]
+        let (start, bytes) = (Instant::now(), entry.bytes().len());
+        let r = self.cache().entry(k.clone()).and_compute_with(|_| {
+            if recent.contains_key(&k) {
+                return moka::ops::compute::Op::Nop;
+            }
+            recent.insert(k.clone(), ());
+            moka::ops::compute::Op::Put(entry)
+        });
+        if !matches!(
+            r,
+            moka::ops::compute::CompResult::Inserted(_)
+                | moka::ops::compute::CompResult::ReplacedWith(_)
+        ) {
+            log::debug!("cache insert key: {} skipped by min_write_interval", k);
+            return Ok(false);
+        }
+        self.inner
+            .state
+            .stats
+            .inserts
+            .fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "trace-ops")]
+        if self.inner.config.on_op.is_some() {
+            self.trace_bytes(OpKind::Insert, &k, None, bytes, start);
+        }
+        Ok(true)
+    }
+
+    // 删除统一经过这里
+    fn take(&self, k: &str) -> Option<CacheEntry> {
+        #[cfg(feature = "trace-ops")]
+        let start = Instant::now();
+        let v = self.cache().remove(k);
+        #[cfg(feature = "trace-ops")]
+        self.trace(OpKind::Remove, k, Some(v.is_some()), v.as_ref(), start);
+        v
+    }
+
+    #[cfg(feature = "trace-ops")]
+    fn trace(
+        &self,
+        op: OpKind,
+        key: &str,
+        hit: Option<bool>,
+        v: Option<&CacheEntry>,
+        start: Instant,
+    ) {
+        self.trace_bytes(op, key, hit, v.map_or(0, |v| v.bytes().len()), start);
+    }
+
+    #[cfg(feature = "trace-ops")]
+    fn trace_bytes(&self, op: OpKind, key: &str, hit: Option<bool>, bytes: usize, start: Instant) {
+        if let Some(on_op) = &self.inner.config.on_op {
+            on_op(&OpTrace {
+                op,
+                key,
+                hit,
+                bytes,
+                elapsed: start.elapsed(),
+            });
+        }
+    }
+
+    // 按配置构造 entry, share_bytes 开启时字节转为共享的 Bytes (不复制)
+    fn new_entry(&self, exp: Expiration, b: Vec<u8>) -> CacheEntry {
+        #[cfg(feature = "bytes")]
+        if self.inner.config.share_bytes {
+            let mut entry = CacheEntry::new(exp, Vec::new());
+            entry.shared = Some(Bytes::from(b));
+            return entry;
+        }
+        CacheEntry::new(exp, b)
+    }
+
+    // 用新的字节替换已有 entry 的数据, 保留其余属性 (类型名、内容类型、弱引用、校验和、persist 标记);
+    // 带校验和的 entry 按新字节重新计算 CRC32
+    fn rebuild_entry(&self, old: &CacheEntry, exp: Expiration, b: Vec<u8>) -> CacheEntry {
+        let b = if old.checksum { with_crc32(&b) } else { b };
+        let mut entry = self.new_entry(exp, b);
+        entry.type_tag = old.type_tag.clone();
+        entry.content_type = old.content_type;
+        entry.weak = old.weak.clone();
+        entry.checksum = old.checksum;
+        entry.persist = old.persist;
+        entry
+    }
+
+    fn check_key_len(&self, k: &str) -> Result<(), CacheError> {
+        match self.inner.config.max_key_len {
+            Some(max) if k.len() > max => {
+                log::error!("cache key too long: {} > {}", k.len(), max);
+                Err(CacheError::KeyTooLong { len: k.len(), max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn stats(&self) -> &CacheStats {
+        &self.inner.state.stats
+    }
+
+    // 每 N 次操作执行一次维护
+    fn maintain(&self) {
+        let n = self.inner.config.opportunistic_maintenance;
+        if n > 0 {
+            let c = self
+                .inner
+                .ops
+                .fetch_add(1, Ordering::Relaxed)
+                .wrapping_add(1);
+            if c.is_multiple_of(n) {
+                self.cache().run_pending_tasks();
+            }
+        }
+        self.check_soft_threshold();
+    }
+
+    // 序列化, length_prefix 开启时在前面加 4 字节大端长度
+    fn encode<V: Serialize>(&self, value: &V) -> Result<Vec<u8>, EncodeError> {
+        let b = if self.inner.config.deterministic_encoding {
+            self.inner
+                .config
+                .codec
+                .encode(&canonical::to_value(value)?)?
+        } else {
+            self.inner.config.codec.encode(value)?
+        };
+        let b = self.frame(b);
+        let stats = &self.inner.state.stats;
+        stats.encodes.fetch_add(1, Ordering::Relaxed);
+        stats
+            .bytes_encoded
+            .fetch_add(b.len() as u64, Ordering::Relaxed);
+        Ok(b)
+    }
+
+    fn decode<V: DeserializeOwned>(&self, b: &[u8]) -> Result<V, DecodeError> {
+        let value = self.inner.config.codec.decode(self.unframe(b)?)?;
+        let stats = &self.inner.state.stats;
+        stats.decodes.fetch_add(1, Ordering::Relaxed);
+        stats
+            .bytes_decoded
+            .fetch_add(b.len() as u64, Ordering::Relaxed);
+        Ok(value)
+    }
+
+    // 按 bincode 的错误类型区分截断和类型不符, 其他 codec 的错误信息无法可靠区分, 统一为 Decode
+    fn classify_decode_error(&self, e: DecodeError) -> CacheError {
+        if self.inner.config.codec != Codec::Bincode {
+            return CacheError::Decode(e.to_string());
+        }
+        let msg = e.to_string();
+        match e {
+            DecodeError::UnexpectedEnd { .. }
+            | DecodeError::Other("missing length prefix" | "length prefix mismatch") => {
+                CacheError::Truncated(msg)
+            }
+            DecodeError::InvalidIntegerType { .. }
+            | DecodeError::NonZeroTypeIsZero { .. }
+            | DecodeError::UnexpectedVariant { .. }
+            | DecodeError::Utf8 { .. }
+            | DecodeError::InvalidCharEncoding(_)
+            | DecodeError::InvalidBooleanValue(_)
+            | DecodeError::ArrayLengthMismatch { .. }
+            | DecodeError::OutsideUsizeRange(_)
+            | DecodeError::EmptyEnum { .. }
+            | DecodeError::InvalidDuration { .. }
+            | DecodeError::InvalidSystemTime { .. }
+            | DecodeError::CStringNulError { .. }
+            // serde 的 invalid type/value/length、unknown variant 等
+            | DecodeError::OtherString(_) => CacheError::TypeMismatch(msg),
+            _ => CacheError::Decode(msg),
+        }
+    }
+
+    fn frame(&self, b: Vec<u8>) -> Vec<u8> {
+        if !self.inner.config.length_prefix {
+            return b;
+        }
+        let mut framed = Vec::with_capacity(b.len() + 4);
+        framed.extend_from_slice(&(b.len() as u32).to_be_bytes());
+        framed.extend_from_slice(&b);
+        framed
+    }
+
+    fn unframe<'a>(&self, b: &'a [u8]) -> Result<&'a [u8], DecodeError> {
+        if !self.inner.config.length_prefix {
+            return Ok(b);
+        }
+        let Some((len, payload)) = b.split_first_chunk::<4>() else {
+            return Err(DecodeError::Other("missing length prefix"));
+        };
+        if u32::from_be_bytes(*len) as usize != payload.len() {
+            return Err(DecodeError::Other("length prefix mismatch"));
+        }
+        Ok(payload)
+    }
+
+    fn check_soft_threshold(&self) {
+        let (Some(threshold), Some(callback)) = (
+            self.inner.config.soft_capacity_threshold,
+            self.inner.config.on_soft_threshold.as_ref(),
+        ) else {
+            return;
+        };
+        let cap = self.capacity();
+        let used = self.cache().weighted_size();
+        let limit = cap as f64 * threshold;
+        if used as f64 >= limit {
+            if !self.inner.soft_fired.swap(true, Ordering::AcqRel) {
+                callback(used, cap);
+            }
+        } else if (used as f64) < limit * 0.9 {
+            self.inner.soft_fired.store(false, Ordering::Release);
+        }
+    }
+
+    pub fn insert<K, V>(&self, key: K, value: V, exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        self.try_insert(key, value, exp)?;
+        Ok(())
+    }
+
+    // key 不存在时才写入, 返回是否写入; 判断和写入在 moka 的 entry 锁内完成
+    pub fn insert_if_absent<K, V>(&self, key: K, value: V, exp: Expiration) -> Result<bool>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let mut cooling = false;
+        let entry = self.cache().entry(k.clone()).or_try_insert_with(|| {
+            let entry = self.encode(&value).map(|b| self.new_entry(exp, b))?;
+            if let Some(recent) = &self.inner.recent_writes {
+                if recent.contains_key(&k) {
+                    cooling = true;
+                    return Err(anyhow!("skipped by min_write_interval"));
+                }
+                recent.insert(k.clone(), ());
+            }
+            Ok(entry)
+        });
+        if cooling {
+            log::debug!("cache insert key: {} skipped by min_write_interval", k);
+            self.maintain();
+            return Ok(false);
+        }
+        let entry = entry.map_err(|e| anyhow!("cache serialize error: {}", e))?;
+        let inserted = entry.is_fresh();
+        if inserted {
+            self.inner
+                .state
+                .stats
+                .inserts
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        self.maintain();
+        Ok(inserted)
+    }
+
+    // 批量 insert_if_absent, 返回新写入的个数, 已存在的 key 跳过; 遇到第一个错误即返回
+    pub fn multi_set_if_absent<K, V, I>(&self, entries: I, exp: Expiration) -> Result<usize>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut n = 0;
+        for (k, v) in entries {
+            if self.insert_if_absent(k, v, exp)? {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    // 准入控制: 写入后权重总和会超过容量 (从而触发驱逐) 时拒绝写入, 返回 Ok(false);
+    // 同 key 的旧 entry 的权重按被替换计算. 检查前会执行一次维护任务让 weighted_size 准确,
+    // 但检查和写入之间并发的写入仍可能导致超出, 只是尽力而为
+    pub fn try_insert_without_evicting<K, V>(
+        &self,
+        key: K,
+        value: V,
+        exp: Expiration,
+    ) -> Result<bool>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let entry = self.new_entry(exp, b);
+        let weight = |v: &CacheEntry| u64::from(entry_weight(&self.inner.config, &k, v));
+        self.cache().run_pending_tasks();
+        let old = self.cache().get(&k).map_or(0, |v| weight(&v));
+        let size = self.cache().weighted_size().saturating_sub(old) + weight(&entry);
+        if size > self.capacity() {
+            log::debug!(
+                "cache insert key: {} refused, would evict ({} > {})",
+                k,
+                size,
+                self.capacity()
+            );
+            return Ok(false);
+        }
+        let written = self.write(k, entry)?;
+        self.maintain();
+        Ok(written)
+    }
+
+    // 批量写入已序列化的数据, 要么全部写入要么都不写: 写入前按 weigher 计算整批写入后的权重总和
+    // (同 key 的旧 entry 按被替换计算, 批内重复的 key 以最后一个为准), 超过容量时返回
+    // CacheError::CapacityExceeded 且不写入任何 entry. 只保证写入时的原子性: 之后的容量驱逐、过期仍可能
+    // 只删除其中一部分, 检查和写入之间并发的写入也可能导致超出
+    pub fn insert_all_or_none(&self, entries: Vec<(String, CacheData)>) -> Result<()> {
+        for (k, _) in entries.iter() {
+            self.check_key_len(k)?;
+        }
+        let entries: Vec<(String, CacheEntry)> = entries
+            .into_iter()
+            .map(|(k, (exp, b))| {
+                let entry = self.new_entry(exp, b);
+                (k, entry)
+            })
+            .collect();
+        let weight = |k: &str, v: &CacheEntry| u64::from(entry_weight(&self.inner.config, k, v));
+        let mut added: HashMap<&str, u64> = HashMap::new();
+        for (k, v) in entries.iter() {
+            added.insert(k, weight(k, v));
+        }
+        self.cache().run_pending_tasks();
+        let old: u64 = added
+            .keys()
+            .filter_map(|k| self.cache().get(*k).map(|v| weight(k, &v)))
+            .sum();
+        let required =
+            self.cache().weighted_size().saturating_sub(old) + added.values().sum::<u64>();
+        let capacity = self.capacity();
+        if required > capacity {
+            log::debug!(
+                "cache insert {} entries refused, would evict ({} > {})",
+                entries.len(),
+                required,
+                capacity
+            );
+            return Err(CacheError::CapacityExceeded { required, capacity }.into());
+        }
+        for (k, entry) in entries {
+            self.put(k, entry)?;
+        }
+        self.maintain();
+        Ok(())
+    }
+
+    // 批量写入, 遇到第一个错误即返回
+    pub fn extend<K, V, I>(&self, entries: I, exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (k, v) in entries {
+            self.insert(k, v, exp)?;
+        }
+        Ok(())
+    }
+
+    // 批量写入, 每个 entry 有自己的过期时间 (例如从 Redis MGET + TTL 导入);
+    // 单个 entry 序列化失败时记录日志并继续, 返回成功写入的个数
+    pub fn insert_many_with_individual_exp<K, V, I>(&self, entries: I) -> Result<usize>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+        I: IntoIterator<Item = (K, V, Expiration)>,
+    {
+        let mut n = 0;
+        for (k, v, exp) in entries {
+            let k = k.into();
+            match self.insert(k.as_str(), v, exp) {
+                Ok(()) => n += 1,
+                Err(e) => log::error!("key: {} insert skipped: {}", k, e),
+            }
+        }
+        Ok(n)
+    }
+
+    // 批量装载已序列化的数据 (例如从快照恢复), 返回写入的个数; ttl 从装载时重新计时.
+    // replace_existing 为 false 时跳过已存在的 key.
+    // 装载期间持有 load_gate 写锁, get/contains_key 等读操作会等待装载完成, 看到的是装载前或装载后的状态;
+    // 这只是尽力而为: insert/remove、过期和容量驱逐不受 gate 限制, 回调中也不要调用本 client 的读操作
+    pub fn bulk_load(&self, entries: Vec<(String, CacheData)>, replace_existing: bool) -> usize {
+        let _gate = self
+            .inner
+            .load_gate
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut n = 0;
+        for (k, (exp, b)) in entries {
+            if !replace_existing && self.cache().contains_key(&k) {
+                continue;
+            }
+            if self.put(k, self.new_entry(exp, b)).is_ok() {
+                n += 1;
+            }
+        }
+        n
+    }
+
+    // 冻结读取, 用于批量重建一组相关的 entry (例如配置热加载): freeze 之后其他线程的 get 等读取等待 thaw,
+    // 因此读到的是 freeze 之前或 thaw 之后的数据, 不会读到新旧混合的结果. 调用 freeze 的线程自己的读取不受影响.
+    // 这只是尽力而为: 读取最多等待 FREEZE_MAX_WAIT; for_each、get_peek 等遍历接口不等待;
+    // freeze 之前已经开始的读取可能读到部分更新. 写入不受影响
+    pub fn freeze(&self) {
+        let mut by = self
+            .inner
+            .frozen_by
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *by = Some(std::thread::current().id());
+        self.inner.frozen.store(true, Ordering::Release);
+    }
+
+    pub fn thaw(&self) {
+        let mut by = self
+            .inner
+            .frozen_by
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        *by = None;
+        self.inner.frozen.store(false, Ordering::Release);
+        self.inner.thawed.notify_all();
+    }
+
+    // 把所有 entry 按原始字节复制到 dest (不重新序列化), Expiration 不变, ttl 在 dest 中重新计时;
+    // 剩余 ttl 已为 0 的跳过, 返回复制的个数. 两边的 codec、length_prefix 等配置需要一致, 否则 dest 读取会失败.
+    // entry 按 dest 的配置重新创建: dest 没有开启 checksums 时去掉校验和, 超过 dest 的 max_key_len 的 key
+    // 记录日志后跳过; insert_indexed 的索引复制到 dest, insert_with_on_evict 的回调只会调用一次, 仍留在原缓存.
+    // 复制期间和 bulk_load 一样持有 dest 的 load_gate 写锁
+    pub fn copy_all_to(&self, dest: &CacheClient) -> Result<usize> {
+        let _gate = dest
+            .inner
+            .load_gate
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut n = 0;
+        for (k, v) in self.cache().iter() {
+            if v.remaining() == Some(Duration::ZERO) {
+                continue;
+            }
+            if !v.checksum_ok() {
+                log::error!("key: {} checksum mismatch, skip copy", k);
+                continue;
+            }
+            let checksum = v.checksum && dest.inner.config.checksums;
+            let b = if checksum {
+                with_crc32(v.bytes())
+            } else {
+                v.bytes().to_vec()
+            };
+            let mut entry = dest.new_entry(v.data.0, b);
+            entry.type_tag = v.type_tag.clone();
+            entry.content_type = v.content_type;
+            entry.weak = v.weak.clone();
+            entry.checksum = checksum;
+            entry.persist = v.persist;
+            let version = entry.version;
+            if dest.put(k.as_ref().clone(), entry).is_err() {
+                continue;
+            }
+            let indexes = match self
+                .inner
+                .state
+                .index
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .owners
+                .get(k.as_ref())
+            {
+                Some((owner, indexes)) if *owner == v.version => indexes.clone(),
+                _ => Vec::new(),
+            };
+            dest.inner.state.attach(
+                &k,
+                Registrations {
+                    version,
+                    callbacks: Vec::new(),
+                    indexes,
+                    refresh: false,
+                    access: None,
+                },
+            );
+            n += 1;
+        }
+        dest.maintain();
+        Ok(n)
+    }
+
+    // 按 f 把所有 key 迁移到新 key (例如 user_<id> -> u:<id>), f 返回 None 的 entry 被删除;
+    // value 和剩余 ttl 保持不变, 返回迁移的个数. 迁移不算删除, 不调用 callback 和 on_evict,
+    // insert_with_on_evict、insert_indexed 等注册随 entry 转到新 key. 多个旧 key 映射到同一个新 key 时,
+    // 按旧 key 字典序处理, 排在最后的覆盖前面的 (last wins); 迁移期间和 bulk_load 一样持有 load_gate 写锁
+    // 有新 key 超过 max_key_len 时返回 CacheError::KeyTooLong, 不做任何修改
+    pub fn rekey_all<F>(&self, f: F) -> Result<usize>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let _gate = self
+            .inner
+            .load_gate
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut entries: Vec<(String, CacheEntry, Option<String>)> = self
+            .cache()
+            .iter()
+            .map(|(k, v)| {
+                let new_key = f(&k);
+                (k.as_ref().clone(), v, new_key)
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        // 有新 key 超过 max_key_len 时不做任何修改
+        for (_, _, new_key) in entries.iter() {
+            if let Some(new_key) = new_key {
+                self.check_key_len(new_key)?;
+            }
+        }
+
+        // 先把所有要迁移的 entry 静默删除并取出注册, 再写入新 key,
+        // 避免新 key 恰好是另一个待迁移的旧 key 时被提前覆盖
+        let state = &self.inner.state;
+        let mut n = 0;
+        let mut moved = Vec::with_capacity(entries.len());
+        for (k, mut v, new_key) in entries {
+            let exp = v.remaining_exp();
+            if new_key.as_deref() == Some(k.as_str()) {
+                n += usize::from(exp.is_some());
+                continue;
+            }
+            // 已过期或 f 返回 None 的 entry 按普通删除处理, 正常通知
+            let (Some(new_key), Some(exp)) = (new_key, exp) else {
+                self.cache().invalidate(&k);
+                continue;
+            };
+            let version = v.version;
+            let r = self
+                .cache()
+                .entry(k.clone())
+                .and_compute_with(|old| match old {
+                    Some(old) if old.value().version == version => {
+                        state
+                            .silent
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .insert(version);
+                        moka::ops::compute::Op::Remove
+                    }
+                    _ => moka::ops::compute::Op::Nop,
+                });
+            if !matches!(r, moka::ops::compute::CompResult::Removed(_)) {
+                continue;
+            }
+            v.data.0 = exp;
+            moved.push((new_key, v, state.detach(&k, version)));
+        }
+        for (k, mut v, registrations) in moved {
+            v.inserted_at = Instant::now();
+            // 新 key 已在开始时检查过
+            let _ = self.put(k.clone(), v);
+            state.attach(&k, registrations);
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    // 写入时由 index_fn 计算若干索引值, 之后可用 get_by_index 通过索引值读取;
+    // 索引随该 entry 一起失效 (过期、删除、覆盖、容量驱逐)
+    pub fn insert_indexed<K, V, F>(
+        &self,
+        key: K,
+        value: V,
+        exp: Expiration,
+        index_fn: F,
+    ) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+        F: FnOnce(&V) -> Vec<String>,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let indexes = index_fn(&value);
+        let b = self.encode(&value)?;
+        let entry = self.new_entry(exp, b);
+        let version = entry.version;
+        if !self.write(k.clone(), entry)? {
+            return Ok(());
+        }
+
+        let mut index = self
+            .inner
+            .state
+            .index
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        index.remove_owner(&k);
+        for i in indexes.iter() {
+            index.keys.insert(i.clone(), k.clone());
+        }
+        index.owners.insert(k, (version, indexes));
+        drop(index);
+
+        self.maintain();
+        Ok(())
+    }
+
+    // on_evict 只在这一次写入的 entry 被移除时调用一次 (过期、删除、覆盖、容量驱逐),
+    // 不受 notify_on_replace 影响; 在 eviction listener 中执行, 不要在其中调用本 client 的写操作
+    pub fn insert_with_on_evict<K, V, F>(
+        &self,
+        key: K,
+        value: V,
+        exp: Expiration,
+        on_evict: F,
+    ) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+        F: FnOnce(RemovalCause) + Send + 'static,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let entry = self.new_entry(exp, b);
+        self.inner
+            .state
+            .on_evict
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(k.clone())
+            .or_default()
+            .push((entry.version, Box::new(on_evict)));
+        let version = entry.version;
+        if !self.write(k.clone(), entry)? {
+            // 没有写入, 撤销注册
+            let mut on_evict = self
+                .inner
+                .state
+                .on_evict
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if let Some(callbacks) = on_evict.get_mut(&k) {
+                callbacks.retain(|(v, _)| *v != version);
+                if callbacks.is_empty() {
+                    on_evict.remove(&k);
+                }
+            }
+        }
+        self.maintain();
+        Ok(())
+    }
+
+    // 只保存 Weak<V>, 其他地方的 Arc<V> 全部释放后该 entry 自动失效 (get_weak 返回 None 时删除 key);
+    // 失效前 contains_key/entry_count 仍会计入该 entry. get/try_get 读取会返回 ContentTypeMismatch
+    pub fn insert_with_weak_reference<K, V>(
+        &self,
+        key: K,
+        value: Arc<V>,
+        exp: Expiration,
+    ) -> Result<()>
+    where
+        K: Into<String>,
+        V: Send + Sync + 'static,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let mut entry = CacheEntry::new(exp, Vec::new());
+        entry.content_type = ContentType::Weak;
+        entry.weak = Some(Arc::new(Arc::downgrade(&value)));
+        self.write(k, entry)?;
+        self.maintain();
+        Ok(())
+    }
+
+    // 读取弱引用, 类型不匹配返回 None; 原对象已释放时删除该 key 并返回 None
+    pub fn get_weak<K, V>(&self, key: K) -> Option<Arc<V>>
+    where
+        K: Into<String>,
+        V: Send + Sync + 'static,
+    {
+        let k = key.into();
+        let v = self.lookup(&k);
+        self.maintain();
+        let weak = v?.weak?;
+        let Some(weak) = weak.downcast_ref::<std::sync::Weak<V>>() else {
+            log::error!("key: {} weak reference type mismatch", k);
+            return None;
+        };
+        let value = weak.upgrade();
+        if value.is_none() {
+            self.take(&k);
+        }
+        value
+    }
+
+    pub fn get_by_index<V>(&self, index_value: &str) -> Option<(Expiration, V)>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        let key = {
+            let index = self
+                .inner
+                .state
+                .index
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            index.keys.get(index_value)?.clone()
+        };
+        let v = self.get(&key);
+        if v.is_none() && !self.cache().contains_key(&key) {
+            // 主 key 已经不存在, 清理残留的索引
+            let mut index = self
+                .inner
+                .state
+                .index
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            if index.keys.get(index_value).is_some_and(|k| *k == key) {
+                index.keys.remove(index_value);
+            }
+        }
+        v
+    }
+
+    pub fn insert_duration<K, V>(&self, key: K, value: V, duration: Duration) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        self.insert(key, value, Expiration::from(duration))
+    }
+
+    // 返回 false 表示距离上次写入不足 min_write_interval, 本次写入被丢弃
+    pub fn try_insert<K, V>(&self, key: K, value: V, exp: Expiration) -> Result<bool>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let written = self.write(k, self.new_entry(exp, b))?;
+        self.maintain();
+        Ok(written)
+    }
+
+    // 写入时在数据前加 CRC32, get/try_get 读取时校验, 不符返回 CacheError::ChecksumMismatch.
+    // 需要 CacheBuilder::enable_checksums, 否则等同 insert; 只有 try_get 路径做校验
+    pub fn insert_with_checksum<K, V>(&self, key: K, value: V, exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        if !self.inner.config.checksums {
+            return self.insert(key, value, exp);
+        }
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let mut entry = self.new_entry(exp, with_crc32(&b));
+        entry.checksum = true;
+        self.write(k, entry)?;
+        self.maintain();
+        Ok(())
+    }
+
+    // 写入只在本进程内有意义的 entry (临时 token、文件描述符相关的 key 等), 导出到文件时总是跳过,
+    // 不受导出时的过滤条件影响. 标记随 entry 保存, 被同 key 的普通 insert 覆盖后清除
+    pub fn insert_ephemeral<K, V>(&self, key: K, value: V, exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let mut entry = self.new_entry(exp, b);
+        entry.persist = false;
+        self.write(k, entry)?;
+        self.maintain();
+        Ok(())
+    }
+
+    // key 不存在时返回 false
+    pub fn is_ephemeral(&self, key: &str) -> bool {
+        self.lookup(&key.to_string()).is_some_and(|v| !v.persist)
+    }
+
+    // 写入并返回写入前的状态: 新 key 为 Inserted, 序列化后字节不同为 Updated,
+    // 字节相同为 Extended (只更新过期时间并重新计时). 比较和写入在 moka 的 entry 锁内完成;
+    // 需要字节比较可靠时 value 中的 map 应开启 deterministic_encoding
+    pub fn insert_or_extend_ttl<K, V>(
+        &self,
+        key: K,
+        value: V,
+        exp: Expiration,
+    ) -> Result<InsertResult>
+    where
+        K: Into<String>,
+        V: Serialize + Sync + Send,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let mut result = InsertResult::Inserted;
+        self.cache().entry(k).and_compute_with(|old| {
+            if let Some(old) = old {
+                let old = old.into_value();
+                result = if old.bytes() == b.as_slice() {
+                    InsertResult::Extended
+                } else {
+                    let (old_exp, old_value) = old.into_data();
+                    InsertResult::Updated { old_value, old_exp }
+                };
+            }
+            moka::ops::compute::Op::Put(self.new_entry(exp, b))
+        });
+        self.inner
+            .state
+            .stats
+            .inserts
+            .fetch_add(1, Ordering::Relaxed);
+        self.maintain();
+        Ok(result)
+    }
+
+    pub fn get<K, V>(&self, key: K) -> Option<(Expiration, V)>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self.try_get(key) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    // Ok(None) 表示 key 不存在, Err 表示反序列化失败
+    pub fn try_get<K, V>(&self, key: K) -> Result<Option<(Expiration, V)>, CacheError>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let k = key.into();
+        let v = self.lookup(&k);
+        self.maintain();
+        let Some(v) = v else {
+            for f in self.inner.config.on_miss.iter() {
+                f(&k);
+            }
+            return Ok(None);
+        };
+        if v.content_type != ContentType::Encoded {
+            return Err(CacheError::ContentTypeMismatch(v.content_type));
+        }
+        if !v.checksum_ok() {
+            return Err(CacheError::ChecksumMismatch);
+        }
+        let value = self
+            .decode::<V>(v.bytes())
+            .map_err(|e| self.classify_decode_error(e))?;
+        Ok(Some((v.data.0, value)))
+    }
+
+    // 反序列化到临时值并交给 f 借用, 只返回 f 提取的结果 (例如大结构中的一个字段), 临时值在返回前释放.
+    // 仍然需要完整反序列化整个 value, 省去的只是把它返回给调用方; V 需要 DeserializeOwned,
+    // 无法借用缓存中的字节做零拷贝解析 (moka 的 get 返回的是 entry 的拷贝, 开启 share_bytes 时只复制引用).
+    // key 不存在或无法解析时返回 None, 解析失败记录日志
+    pub fn with_value<K, V, R, F>(&self, key: K, f: F) -> Option<R>
+    where
+        K: Into<String>,
+        V: DeserializeOwned,
+        F: FnOnce(&V) -> R,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        if v.content_type != ContentType::Encoded || !v.checksum_ok() {
+            log::error!("cache with_value error: entry is not readable");
+            return None;
+        }
+        match self.decode::<V>(v.bytes()) {
+            Ok(value) => Some(f(&value)),
+            Err(e) => {
+                log::error!("cache deserialize error: {}", e);
+                None
+            }
+        }
+    }
+
+    // 读取但不更新访问记录 (不影响 LFU/LRU 的淘汰顺序, 也不计入 stats), 用于预热检查和监控.
+    // moka 0.12 没有 peek, 这里通过遍历查找, 复杂度 O(n), 不要在热路径上使用;
+    // 读取期间并发写入的新值可能看不到
+    pub fn get_peek<K, V>(&self, key: K) -> Option<V>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let k = key.into();
+        let (_, v) = self.cache().iter().find(|(key, _)| **key == k)?;
+        if self.inner.config.strict_expiry && v.remaining() == Some(Duration::ZERO) {
+            return None;
+        }
+        match self.decode::<V>(v.bytes()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::error!("key: {} deserialize error: {}", k, e);
+                None
+            }
+        }
+    }
+
+    // 反序列化失败时返回 Right((错误, 原始字节)), 便于调用方自行迁移旧格式的数据
+    pub fn get_deserialized_or_raw<K, V>(&self, key: K) -> Option<Either<V, (DecodeError, Vec<u8>)>>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        match self.decode::<V>(v.bytes()) {
+            Ok(value) => Some(Either::Left(value)),
+            Err(e) => {
+                let raw = self.unframe(v.bytes()).unwrap_or(v.bytes()).to_vec();
+                Some(Either::Right((e, raw)))
+            }
+        }
+    }
+
+    // 不存在时调用 f 计算 value 以及它的过期时间 (如 token 自带有效期), 并发未命中只计算一次.
+    // 读取和 get 一样经过 lookup (strict_expiry、freeze 等), 缓存的值无法解析时重新计算;
+    // 开启 loader_failure_ttl 时, 同一 key 的 loader 刚失败过则不调用 f, 返回 CacheError::LoaderBackoff
+    pub fn get_or_insert_with_exp<K, V, F>(&self, key: K, f: F) -> Result<(Expiration, V)>
+    where
+        K: Into<String>,
+        V: Serialize + DeserializeOwned + Sync + Send,
+        F: FnOnce() -> (V, Expiration),
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        self.get_or_load(k.clone(), || {
+            self.check_loader_backoff(&k)?;
+            Ok(f())
+        })
+    }
+
+    // 不存在时调用可能失败的 loader 并写入, 并发未命中只调用一次, 读取规则同 get_or_insert_with_exp;
+    // loader 失败时不写入, 等待的调用者各自重新尝试. 开启 loader_failure_ttl 时, 失败后的一段时间内
+    // 同一 key 未命中直接返回 CacheError::LoaderBackoff, 等待者因此也不会再调用 loader
+    pub fn get_or_insert_try_with<K, V, F>(&self, key: K, exp: Expiration, loader: F) -> Result<V>
+    where
+        K: Into<String>,
+        V: Serialize + DeserializeOwned + Sync + Send,
+        F: FnOnce() -> Result<V>,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let (_, value) = self.get_or_load(k.clone(), || {
+            self.check_loader_backoff(&k)?;
+            let value = loader().inspect_err(|e| self.record_loader_failure(&k, e))?;
+            self.clear_loader_failure(&k);
+            Ok::<_, anyhow::Error>((value, exp))
+        })?;
+        Ok(value)
+    }
+
+    // 不存在时写入 V::default() 并返回, 类似 HashMap::entry().or_default()
+    pub fn get_or_default<K, V>(&self, key: K, exp: Expiration) -> Result<V>
+    where
+        K: Into<String>,
+        V: Default + Serialize + DeserializeOwned + Sync + Send,
+    {
+        let (_, value) = self.get_or_insert_with_exp(key, || (V::default(), exp))?;
+        Ok(value)
+    }
+
+    // 缓存解析结果 (如编译后的正则、模板), 只缓存 Ok; 并发未命中时只解析一次, 失败时各自返回自己的错误.
+    // key 为 "get_or_parse:{类型名}:{source 的 64 位哈希}", 长度与 source 无关; 不同目标类型之间不会冲突,
+    // 不同 source 只在哈希碰撞时冲突. key 超过 max_key_len 时直接解析, 不写入缓存; 普通 key 不要使用 "get_or_parse:" 前缀
+    pub fn get_or_parse<V, E, F>(
+        &self,
+        source: &str,
+        exp: Expiration,
+        f: F,
+    ) -> std::result::Result<(Expiration, V), E>
+    where
+        V: Serialize + DeserializeOwned + Sync + Send,
+        F: FnOnce(&str) -> std::result::Result<V, E>,
+    {
+        use std::hash::{DefaultHasher, Hasher};
+        let mut hasher = DefaultHasher::new();
+        hasher.write(source.as_bytes());
+        let k = format!(
+            "get_or_parse:{}:{:016x}",
+            std::any::type_name::<V>(),
+            hasher.finish()
+        );
+        if let Err(e) = self.check_key_len(&k) {
+            log::warn!("get_or_parse skip cache: {}", e);
+            return f(source).map(|v| (exp, v));
+        }
+        self.get_or_load(k, || f(source).map(|v| (v, exp)))
+    }
+
+    // get_or_* 共用: 命中且可以读取 (与 get 一样等待 freeze/bulk_load, strict_expiry 下过期视为未命中,
+    // 内容类型和校验和不符、无法解析都视为未命中并删除该 entry) 时直接返回; 否则调用 loader 写入.
+    // 并发未命中只有一个调用者执行 loader, 其他调用者等待后读取它写入的值;
+    // loader 失败时不写入, 等待者各自重新尝试, 因此每个调用者得到的是自己的错误.
+    // 写入前编码失败时记录日志, 返回 loader 的结果但不写入缓存
+    fn get_or_load<V, E, F>(&self, k: String, loader: F) -> std::result::Result<(Expiration, V), E>
+    where
+        V: Serialize + DeserializeOwned + Sync + Send,
+        F: FnOnce() -> std::result::Result<(V, Expiration), E>,
+    {
+        let mut loader = Some(loader);
+        let mut outcome = None;
+        loop {
+            if let Some(v) = self.lookup(&k) {
+                let value = (v.content_type == ContentType::Encoded && v.checksum_ok())
+                    .then(|| self.decode::<V>(v.bytes()).ok())
+                    .flatten();
+                if let Some(value) = value {
+                    self.maintain();
+                    return Ok((v.data.0, value));
+                }
+                log::error!("key: {} cached value is not readable, reload", k);
+                let version = v.version;
+                self.cache()
+                    .entry(k.clone())
+                    .and_compute_with(|old| match old {
+                        Some(old) if old.value().version == version => {
+                            moka::ops::compute::Op::Remove
+                        }
+                        _ => moka::ops::compute::Op::Nop,
+                    });
+            }
+            let r = self.cache().entry(k.clone()).or_try_insert_with(|| {
+                // loader 只会被执行一次: 执行后 outcome 一定有值, 下面直接返回
+                let loader = loader.take().ok_or(())?;
+                match loader() {
+                    Ok((value, exp)) => {
+                        let b = self.encode(&value);
+                        outcome = Some(Ok((exp, value)));
+                        b.map(|b| self.new_entry(exp, b))
+                            .map_err(|e| log::error!("key: {} serialize error: {}", k, e))
+                    }
+                    Err(e) => {
+                        outcome = Some(Err(e));
+                        Err(())
+                    }
+                }
+            });
+            if let Some(outcome) = outcome.take() {
+                if r.is_ok() {
+                    self.inner
+                        .state
+                        .stats
+                        .inserts
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                self.maintain();
+                return outcome;
+            }
+            // 没有执行 loader: 其他调用者刚写入 (下一轮按命中读取), 或它的 loader 失败了 (下一轮自己执行)
+        }
+    }
+
+    fn check_loader_backoff(&self, k: &str) -> Result<(), CacheError> {
+        let Some(ttl) = self.inner.config.loader_failure_ttl else {
+            return Ok(());
+        };
+        let failures = self
+            .inner
+            .loader_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        match failures.get(k) {
+            Some((at, error)) if at.elapsed() < ttl => Err(CacheError::LoaderBackoff {
+                error: error.clone(),
+                retry_after: ttl.saturating_sub(at.elapsed()),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_loader_failure(&self, k: &str, e: &anyhow::Error) {
+        let Some(ttl) = self.inner.config.loader_failure_ttl else {
+            return;
+        };
+        let mut failures = self
+            .inner
+            .loader_failures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // 顺便清理已经过了 ttl 的记录
+        failures.retain(|_, (at, _)| at.elapsed() < ttl);
+        failures.insert(k.to_string(), (Instant::now(), e.to_string()));
+    }
+
+    fn clear_loader_failure(&self, k: &str) {
+        if self.inner.config.loader_failure_ttl.is_some() {
+            self.inner
+                .loader_failures
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(k);
+        }
+    }
+
+    // 未命中时调用 loader, 失败后间隔 backoff 重试, 最多重试 max_retries 次, 全部失败返回最后一次的错误;
+    // 开启 loader_failure_ttl 时, 全部失败后的一段时间内同一 key 返回 CacheError::LoaderBackoff
+    pub fn get_or_insert_with_retry<K, V, F>(
+        &self,
+        key: K,
+        exp: Expiration,
+        mut loader: F,
+        max_retries: u32,
+        backoff: Duration,
+    ) -> Result<V>
+    where
+        K: Into<String>,
+        V: Serialize + DeserializeOwned + Sync + Send,
+        F: FnMut() -> Result<V>,
+    {
+        let k = key.into();
+        if let Ok(Some((_, v))) = self.try_get(k.as_str()) {
+            return Ok(v);
+        }
+        self.check_loader_backoff(&k)?;
+        let mut attempt = 0;
+        loop {
+            match loader() {
+                Ok(v) => {
+                    self.clear_loader_failure(&k);
+                    self.insert(k, &v, exp)?;
+                    return Ok(v);
+                }
+                Err(e) if attempt >= max_retries => {
+                    self.record_loader_failure(&k, &e);
+                    return Err(e);
+                }
+                Err(e) => {
+                    attempt += 1;
+                    log::warn!("key: {} loader error: {}, retry: {}", k, e, attempt);
+                    std::thread::sleep(backoff);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn get_or_insert_with_retry_async<K, V, F, Fut>(
+        &self,
+        key: K,
+        exp: Expiration,
+        mut loader: F,
+        max_retries: u32,
+        backoff: Duration,
+    ) -> Result<V>
+    where
+        K: Into<String>,
+        V: Serialize + DeserializeOwned + Sync + Send,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<V>>,
+    {
+        let k = key.into();
+        if let Ok(Some((_, v))) = self.try_get(k.as_str()) {
+            return Ok(v);
+        }
+        self.check_loader_backoff(&k)?;
+        let mut attempt = 0;
+        loop {
+            match loader().await {
+                Ok(v) => {
+                    self.clear_loader_failure(&k);
+                    self.insert(k, &v, exp)?;
+                    return Ok(v);
+                }
+                Err(e) if attempt >= max_retries => {
+                    self.record_loader_failure(&k, &e);
+                    return Err(e);
+                }
+                Err(e) => {
+                    attempt += 1;
+                    log::warn!("key: {} loader error: {}, retry: {}", k, e, attempt);
+                    tokio::time::sleep(backoff).await;
+                }
+            }
+        }
+    }
+
+    // 数据结构变更后迁移旧数据: 先按 VNew 解析, 失败再按 VOld 解析并用 migration_fn 转换后写回;
+    // 两者都失败则删除该 key 并返回 Err. 注意 bincode 不带类型信息, 新旧结构兼容时 VNew 可能直接解析成功
+    pub fn migrate<K, VOld, VNew, F>(&self, key: K, migration_fn: F) -> Result<Option<VNew>>
+    where
+        K: Into<String>,
+        VOld: DeserializeOwned + Sync + Send,
+        VNew: Serialize + DeserializeOwned + Sync + Send,
+        F: FnOnce(VOld) -> Result<VNew>,
+    {
+        let k = key.into();
+        let Some(v) = self.cache().get(&k) else {
+            return Ok(None);
+        };
+        let exp = v.data.0;
+        if let Ok(value) = self.decode::<VNew>(v.bytes()) {
+            return Ok(Some(value));
+        }
+        let old = match self.decode::<VOld>(v.bytes()) {
+            Ok(old) => old,
+            Err(e) => {
+                self.cache().invalidate(&k);
+                return Err(anyhow!("key: {} migrate deserialize error: {}", k, e));
+            }
+        };
+        let value = migration_fn(old)?;
+        let b = self.encode(&value)?;
+        self.put(k, self.rebuild_entry(&v, exp, b))?;
+        Ok(Some(value))
+    }
+
+    // 批量读取为 map, 只包含命中且解析成功的 key; 解析失败的记 WARN 日志后跳过.
+    // 调用方无法区分未命中和解析失败, map.len() < keys.len() 只说明有 key 没有取到, 需要区分时用 try_get_batch
+    pub fn get_many_typed_map<V>(&self, keys: &[&str]) -> HashMap<String, V>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        let mut map = HashMap::with_capacity(keys.len());
+        for k in keys {
+            match self.try_get::<_, V>(*k) {
+                Ok(Some((_, value))) => {
+                    map.insert(k.to_string(), value);
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("key: {} skipped: {}", k, e),
+            }
+        }
+        map
+    }
+
+    // 尽力而为的多 key 一致读取: 读取全部 key 后再核对每个 entry 的写入版本 (inserted_at),
+    // 读取期间有 key 被改写则重试, 最多 CONSISTENT_READ_RETRIES 次, 仍不一致返回 None.
+    // 任一 key 不存在或反序列化失败也返回 None. moka 没有多 key 事务, 这只能避免读取过程中
+    // 发生改写的撕裂读; 写入方先写 a、后写 b 时, 在两次写入之间读取仍会得到新 a 和旧 b
+    pub fn get_consistent<V>(&self, keys: &[&str]) -> Option<Vec<(Expiration, V)>>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        for _ in 0..CONSISTENT_READ_RETRIES {
+            let mut entries = Vec::with_capacity(keys.len());
+            for k in keys {
+                entries.push(self.lookup(&k.to_string())?);
+            }
+            let unchanged = keys.iter().zip(entries.iter()).all(|(k, v)| {
+                self.cache()
+                    .get(*k)
+                    .is_some_and(|now| now.inserted_at == v.inserted_at)
+            });
+            if !unchanged {
+                continue;
+            }
+            let mut values = Vec::with_capacity(keys.len());
+            for (k, v) in keys.iter().zip(entries) {
+                match self.decode::<V>(v.bytes()) {
+                    Ok(value) => values.push((v.data.0, value)),
+                    Err(e) => {
+                        log::error!("key: {} deserialize error: {}", k, e);
+                        return None;
+                    }
+                }
+            }
+            return Some(values);
+        }
+        log::warn!("get_consistent: keys kept changing, give up: {:?}", keys);
+        None
+    }
+
+    // 批量读取, 每个 key 单独返回: 命中、未命中或反序列化失败
+    #[allow(clippy::type_complexity)]
+    pub fn try_get_batch<V>(
+        &self,
+        keys: &[&str],
+    ) -> Vec<(String, Result<Option<(Expiration, V)>, CacheError>)>
+    where
+        V: DeserializeOwned + Sync + Send,
+    {
+        keys.iter()
+            .map(|k| (k.to_string(), self.try_get(*k)))
+            .collect()
+    }
+
+    // 直接保存 Bytes, 不做序列化也不复制 (不加长度前缀)
+    #[cfg(feature = "bytes")]
+    pub fn insert_bytes_shared<K>(&self, key: K, data: Bytes, exp: Expiration)
+    where
+        K: Into<String>,
+    {
+        let mut entry = CacheEntry::new(exp, Vec::new());
+        entry.shared = Some(data);
+        // 超长的 key 已记录日志并丢弃
+        let _ = self.write(key.into(), entry);
+        self.maintain();
+    }
+
+    // 按存储格式读取共享的 Bytes: insert_bytes_shared 写入或开启 share_bytes 时引用计数 +1, 不复制;
+    // 其他 entry 会复制一份原始字节. 需要可修改的拷贝时用 get_bytes
+    #[cfg(feature = "bytes")]
+    pub fn get_bytes_shared<K>(&self, key: K) -> Option<(Expiration, Bytes)>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        let skip = if v.checksum { 4 } else { 0 };
+        let b = match v.shared {
+            Some(b) => b.slice(skip.min(b.len())..),
+            None => Bytes::from(v.data.1).slice(skip..),
+        };
+        Some((v.data.0, b))
+    }
+
+    // 写入已经序列化好的字节, 不做 bincode 编码 (length_prefix 开启时同样加前缀)
+    pub fn insert_bytes<K>(&self, key: K, data: Vec<u8>, exp: Expiration)
+    where
+        K: Into<String>,
+    {
+        // 超长的 key 已记录日志并丢弃
+        let _ = self.write(key.into(), self.new_entry(exp, self.frame(data)));
+        self.maintain();
+    }
+
+    // 读取原始字节 (已去掉长度前缀)
+    pub fn get_bytes<K>(&self, key: K) -> Option<(Expiration, Vec<u8>)>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        match self.unframe(v.bytes()) {
+            Ok(b) => Some((v.data.0, b.to_vec())),
+            Err(e) => {
+                log::error!("cache get bytes error: {}", e);
+                None
+            }
+        }
+    }
+
+    // 把大块数据按 chunk_size 拆成多个 entry (key 为 CHUNK_KEY_PREFIX + key:N), key 本身保存清单
+    // (块数、总长度、CRC32), 每块单独计算权重和驱逐. 先写数据块再写清单, 之后删除旧清单多出来的数据块
+    pub fn insert_chunked<K>(
+        &self,
+        key: K,
+        data: &[u8],
+        exp: Expiration,
+        chunk_size: usize,
+    ) -> Result<()>
+    where
+        K: Into<String>,
+    {
+        if chunk_size == 0 {
+            return Err(anyhow!("chunk_size must be greater than 0"));
+        }
+        let k = key.into();
+        self.check_key_len(&k)?;
+        // 最后一块的 key 最长, 写入前先检查, 避免只写入一部分数据块
+        let chunks = u32::try_from(data.len().div_ceil(chunk_size))
+            .map_err(|_| anyhow!("too many chunks"))?;
+        self.check_key_len(&chunk_key(&k, chunks.saturating_sub(1)))?;
+        let old_count = self
+            .cache()
+            .get(&k)
+            .and_then(|v| self.decode::<ChunkManifest>(v.bytes()).ok())
+            .map_or(0, |(count, _, _)| count);
+        let mut count = 0u32;
+        for chunk in data.chunks(chunk_size) {
+            let entry = self.new_entry(exp, self.frame(chunk.to_vec()));
+            self.put(chunk_key(&k, count), entry)?;
+            count += 1;
+        }
+        let manifest: ChunkManifest = (count, data.len() as u64, crc32(data));
+        self.put(k.clone(), self.new_entry(exp, self.encode(&manifest)?))?;
+        // 旧清单不一定可信 (key 原来可能是别的值), 删除到第一个不存在的块为止
+        for i in count..old_count {
+            if self.take(&chunk_key(&k, i)).is_none() {
+                break;
+            }
+        }
+        self.maintain();
+        Ok(())
+    }
+
+    // 重新拼接 insert_chunked 写入的数据. 任何一块被驱逐或过期、或拼接结果与清单不符
+    // (并发写入时读到了新旧两个版本的数据块) 都返回 None, 即干净的未命中, 不会返回损坏的数据
+    pub fn get_chunked<K>(&self, key: K) -> Option<Vec<u8>>
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        let (_, (count, len, sum)) = self.get::<_, ChunkManifest>(k.as_str())?;
+        let mut data = Vec::with_capacity(usize::try_from(len).ok()?);
+        for i in 0..count {
+            let (_, chunk) = self.get_bytes(chunk_key(&k, i))?;
+            data.extend_from_slice(&chunk);
+        }
+        if data.len() as u64 != len || crc32(&data) != sum {
+            log::debug!("cache get chunked key: {} incomplete", k);
+            return None;
+        }
+        Some(data)
+    }
+
+    // 原样保存 JSON 文本 (UTF-8 字节, 不做 codec 编码, 也不校验格式), 用 get_json 读取;
+    // 对这类 entry 调用 get/try_get 会返回 CacheError::ContentTypeMismatch
+    pub fn insert_json<K>(&self, key: K, json: &str, exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let mut entry = self.new_entry(exp, self.frame(json.as_bytes().to_vec()));
+        entry.content_type = ContentType::Json;
+        self.write(k, entry)?;
+        self.maintain();
+        Ok(())
+    }
+
+    // 读取 insert_json 写入的 JSON 文本, 其他方式写入的 entry 返回 None
+    pub fn get_json<K>(&self, key: K) -> Option<String>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v.filter(|v| v.content_type == ContentType::Json)?;
+        let b = self.unframe(v.bytes()).ok()?;
+        String::from_utf8(b.to_vec()).ok()
+    }
+
+    // 从 JSON 文件批量写入, 文件内容为 [{"key": "...", "value": <任意 JSON>}], 每个 value 按 insert_json
+    // 原样保存 JSON 文本; 返回写入的个数. 文件格式错误时不写入任何 entry
+    #[cfg(feature = "json")]
+    pub fn insert_batch_from_json_file(
+        &self,
+        path: &std::path::Path,
+        exp: Expiration,
+    ) -> Result<usize> {
+        #[derive(Deserialize)]
+        struct Item {
+            key: String,
+            value: serde_json::Value,
+        }
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow!("open {} error: {}", path.display(), e))?;
+        let items: Vec<Item> = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| anyhow!("parse {} error: {}", path.display(), e))?;
+        for item in items.iter() {
+            self.check_key_len(&item.key)?;
+        }
+        let n = items.len();
+        for item in items {
+            self.insert_json(item.key, &item.value.to_string(), exp)?;
+        }
+        Ok(n)
+    }
+
+    // 把全部 entry 写入 JSON 文件 (按 key 排序), 返回写入的个数. insert_json 写入的 entry 保存为原 JSON,
+    // 其他 entry 的存储字节保存为 {"key", "value": base64 字符串, "encoding": "base64"},
+    // insert_batch_from_json_file 读回时只会得到这个字符串, 不会还原为原来的字节.
+    // insert_ephemeral 写入的 entry 和弱引用 entry 跳过; 一致性同 export
+    #[cfg(feature = "json")]
+    pub fn export_to_json_file(&self, path: &std::path::Path) -> Result<usize> {
+        let mut entries: Vec<(Arc<String>, CacheEntry)> = self
+            .cache()
+            .iter()
+            .filter(|(_, v)| v.persist && v.content_type != ContentType::Weak)
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let items: Vec<serde_json::Value> = entries
+            .iter()
+            .map(|(k, v)| {
+                let raw = self.unframe(v.bytes()).unwrap_or(v.bytes());
+                let json = (v.content_type == ContentType::Json)
+                    .then(|| serde_json::from_slice::<serde_json::Value>(raw).ok())
+                    .flatten();
+                match json {
+                    Some(value) => serde_json::json!({ "key": k.as_str(), "value": value }),
+                    None => serde_json::json!({
+                        "key": k.as_str(),
+                        "value": base64_encode(raw),
+                        "encoding": "base64",
+                    }),
+                }
+            })
+            .collect();
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow!("create {} error: {}", path.display(), e))?;
+        serde_json::to_writer(std::io::BufWriter::new(file), &items)
+            .map_err(|e| anyhow!("write {} error: {}", path.display(), e))?;
+        Ok(items.len())
+    }
+
+    // 写入时记录 V::TAG, 之后用 get_tagged + TaggedValue::downcast 读取
+    pub fn insert_tagged<K, V>(&self, key: K, value: V, exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+        V: CacheTag + Serialize + Sync + Send,
+    {
+        let k = key.into();
+        self.check_key_len(&k)?;
+        let b = self.encode(&value)?;
+        let mut entry = self.new_entry(exp, b);
+        entry.type_tag = Some(V::TAG.to_string());
+        self.write(k, entry)?;
+        self.maintain();
+        Ok(())
+    }
+
+    // 读取带类型标识的 entry, 没有标识的 entry 返回 None
+    pub fn get_tagged<K>(&self, key: K) -> Option<(Expiration, TaggedValue)>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        let tag = v.type_tag.clone()?;
+        match self.unframe(v.bytes()) {
+            Ok(b) => Some((
+                v.data.0,
+                TaggedValue {
+                    tag,
+                    bytes: b.to_vec(),
+                    codec: self.inner.config.codec,
+                },
+            )),
+            Err(e) => {
+                log::error!("cache get tagged error: {}", e);
+                None
+            }
+        }
+    }
+
+    // 写入原始字节并记录类型名, 由调用方自行序列化, 用于在缓存之上封装多种类型
+    pub fn insert_bytes_with_type_tag<K>(
+        &self,
+        key: K,
+        type_name: &str,
+        data: Vec<u8>,
+        exp: Expiration,
+    ) where
+        K: Into<String>,
+    {
+        let mut entry = self.new_entry(exp, self.frame(data));
+        entry.type_tag = Some(type_name.to_string());
+        // 超长的 key 已记录日志并丢弃
+        let _ = self.write(key.into(), entry);
+        self.maintain();
+    }
+
+    // 返回 (类型名, 原始字节, 过期时间); 不是通过 insert_bytes_with_type_tag 写入的 entry 返回 None
+    pub fn get_bytes_with_type_tag<K>(&self, key: K) -> Option<(String, Vec<u8>, Expiration)>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        let tag = v.type_tag.clone()?;
+        match self.unframe(v.bytes()) {
+            Ok(b) => Some((tag, b.to_vec(), v.data.0)),
+            Err(e) => {
+                log::error!("cache get bytes error: {}", e);
+                None
+            }
+        }
+    }
+
+    // 按存储格式原样读取, length_prefix 开启时包含长度前缀, 可直接写到 socket
+    pub fn get_framed<K>(&self, key: K) -> Option<(Expiration, Vec<u8>)>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        let v = v?;
+        Some((v.data.0, v.bytes().to_vec()))
+    }
+
+    pub fn get_exp<K>(&self, key: K) -> Option<Expiration>
+    where
+        K: Into<String>,
+    {
+        self.lookup(&key.into()).map(|v| v.data.0)
+    }
+
+    // 标记 entry 正在后台刷新 (stale-while-revalidate), 只有第一个调用者返回 true 并负责重新加载,
+    // 其他调用者继续使用旧值. key 不存在时返回 false. 标记在该 key 下次写入 (insert、update_field 等)
+    // 或删除时清除, refresh 等重新计时不清除; 加载失败时调用 clear_refreshing, 否则直到 entry 被替换前都不会再次触发
+    pub fn mark_refreshing(&self, key: &str) -> bool {
+        let Some(v) = self.cache().get(key) else {
+            return false;
+        };
+        let mut refreshing = self
+            .inner
+            .state
+            .refreshing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if refreshing.get(key) == Some(&v.version) {
+            return false;
+        }
+        refreshing.insert(key.to_string(), v.version);
+        true
+    }
+
+    pub fn is_refreshing(&self, key: &str) -> bool {
+        let Some(v) = self.cache().get(key) else {
+            return false;
+        };
+        self.inner
+            .state
+            .refreshing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(key)
+            == Some(&v.version)
+    }
+
+    pub fn clear_refreshing(&self, key: &str) {
+        self.inner
+            .state
+            .refreshing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+
+    pub fn get_exp_or_default<K>(&self, key: K, default: Expiration) -> Expiration
+    where
+        K: Into<String>,
+    {
+        self.get_exp(key).unwrap_or(default)
+    }
+
+    // f 只在 key 不存在时调用
+    pub fn get_exp_or_else<K, F>(&self, key: K, f: F) -> Expiration
+    where
+        K: Into<String>,
+        F: FnOnce() -> Expiration,
+    {
+        self.get_exp(key).unwrap_or_else(f)
+    }
+
+    pub fn get_expiration_kind(&self, key: &str) -> Option<ExpirationKind> {
+        self.get_exp(key).map(|exp| exp.kind())
+    }
+
+    // key 不存在时 is_permanent 和 is_timed 都返回 false
+    pub fn is_permanent(&self, key: &str) -> bool {
+        self.get_expiration_kind(key) == Some(ExpirationKind::Permanent)
+    }
+
+    pub fn is_timed(&self, key: &str) -> bool {
+        self.get_expiration_kind(key) == Some(ExpirationKind::Timed)
+    }
+
+    // 剩余存活时间: 外层 None 表示 key 不存在, Some(None) 表示永不过期, Some(Some(d)) 为剩余时间;
+    // 对应 redis TTL 的 -2 / -1 / N
+    pub fn time_to_live<K>(&self, key: K) -> Option<Option<Duration>>
+    where
+        K: Into<String>,
+    {
+        self.lookup(&key.into()).map(|v| v.remaining())
+    }
+
+    // 一次查询返回 (过期时间, 序列化后的字节数), 不做反序列化, 代替 contains_key + get_exp + get_bytes_len;
+    // moka 的 get 仍会复制一份 entry, 开启 share_bytes 时只复制引用
+    pub fn probe<K>(&self, key: K) -> Option<(Expiration, usize)>
+    where
+        K: Into<String>,
+    {
+        let v = self.lookup(&key.into());
+        self.maintain();
+        v.map(|v| (v.data.0, v.bytes().len()))
+    }
+
+    // 序列化后的字节数, 不做反序列化
+    pub fn get_bytes_len<K>(&self, key: K) -> Option<usize>
+    where
+        K: Into<String>,
+    {
+        self.lookup(&key.into()).map(|v| v.bytes().len())
+    }
+
+    pub fn remove<K>(&self, key: K)
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        #[cfg(feature = "trace-ops")]
+        let start = Instant::now();
+        self.cache().invalidate(&k);
+        #[cfg(feature = "trace-ops")]
+        self.trace_bytes(OpKind::Remove, &k, None, 0, start);
+    }
+
+    // 批量删除, 返回实际存在并被删除的个数
+    pub fn remove_many<K>(&self, keys: &[K]) -> usize
+    where
+        K: AsRef<str>,
+    {
+        self.remove_many_detailed(keys).len()
+    }
+
+    // 批量删除, 返回被删除的 (key, 过期时间), 不存在的 key 不出现在结果中, 顺序不保证与 keys 一致
+    pub fn remove_many_detailed<K>(&self, keys: &[K]) -> Vec<(String, Expiration)>
+    where
+        K: AsRef<str>,
+    {
+        keys.iter()
+            .filter_map(|k| {
+                let k = k.as_ref();
+                self.take(k).map(|v| (k.to_string(), v.data.0))
+            })
+            .collect()
+    }
+
+    // 删除并返回 value, 用于释放 value 关联的外部资源
+    pub fn remove_typed<K, V>(&self, key: K) -> Option<(Expiration, V)>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        match self.try_remove_typed(key) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            }
+        }
+    }
+
+    // 反序列化失败时 entry 同样已被删除
+    pub fn try_remove_typed<K, V>(&self, key: K) -> Result<Option<(Expiration, V)>, CacheError>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let Some(v) = self.take(&key.into()) else {
+            return Ok(None);
+        };
+        let value = self
+            .decode::<V>(v.bytes())
+            .map_err(|e| CacheError::Decode(e.to_string()))?;
+        Ok(Some((v.data.0, value)))
+    }
+
+    pub fn contains_key<K>(&self, key: K) -> bool
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        let _gate = self
+            .inner
+            .load_gate
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        if self.inner.config.strict_expiry {
+            return self
+                .cache()
+                .get(&k)
+                .is_some_and(|v| v.remaining() != Some(Duration::ZERO));
+        }
+        self.cache().contains_key(&k)
+    }
+
+    pub fn check_exp_interval(&self) {
+        self.cache().run_pending_tasks();
+    }
+
+    pub fn refresh<K>(&self, key: K) -> Result<()>
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        let Some(v) = self.cache().get(&k) else {
+            return Err(anyhow!("key: {} not found", k));
+        };
+
+        if v.data.0 == Expiration::Never {
+            return Ok(());
+        }
+
+        let exp = v.data.0;
+        self.reset_exp(k, v, exp);
+        Ok(())
+    }
+
+    // 对所有非 Never 的 entry 执行 refresh (ttl 从现在重新计时), 返回刷新的个数; 已过期但尚未清理的跳过.
+    // 每个 key 在分段锁内处理, 与 get_and_extend/update_field 串行; 遍历之后被重新写入的 entry 不再刷新,
+    // 避免覆盖并发写入的新值
+    pub fn refresh_all(&self) -> Result<usize> {
+        let mut n = 0;
+        for (k, v) in self.cache().iter() {
+            if v.data.0 == Expiration::Never || v.remaining() == Some(Duration::ZERO) {
+                continue;
+            }
+            let _guard = self.key_lock(&k);
+            let exp = v.data.0;
+            if self.reset_exp(k.as_ref().clone(), v, exp) {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    // 用新的过期时间重新计时 (滑动过期), 不改变 value
+    pub fn expire_after_read_reset<K>(&self, key: K, new_exp: Expiration) -> Result<()>
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        let Some(v) = self.cache().get(&k) else {
+            return Err(anyhow!("key: {} not found", k));
+        };
+        self.reset_exp(k, v, new_exp);
+        Ok(())
+    }
+
+    // 读取并把剩余 ttl 延长 extension (例如每次读取 session 续期 30 分钟), Never 不变;
+    // 同一 key 的 get_and_extend 通过分段锁串行, 续期后 get_exp 返回 "剩余 + extension" 并从现在开始计时
+    pub fn get_and_extend<K, V>(&self, key: K, extension: Duration) -> Result<Option<V>>
+    where
+        K: Into<String>,
+        V: DeserializeOwned + Sync + Send,
+    {
+        let k = key.into();
+        let _guard = self.key_lock(&k);
+        let Some(v) = self.lookup(&k) else {
+            return Ok(None);
+        };
+        let value = self
+            .decode::<V>(v.bytes())
+            .map_err(|e| CacheError::Decode(e.to_string()))?;
+        match v.remaining().map(|d| d.saturating_add(extension)) {
+            Some(total) if !total.is_zero() => {
+                self.reset_exp(k, v, Expiration::from_duration(total));
+            }
+            _ => {}
+        }
+        Ok(Some(value))
+    }
+
+    // 只修改 value 中的一个字段: accessor 取得字段的可变引用, f 修改它, 剩余 ttl 保持不变;
+    // key 不存在返回 Ok(false). bincode 无法只改写部分字节, 内部仍会解析并重新编码整个 value,
+    // 省去的是调用方的样板代码; 同一 key 的 update_field 通过分段锁串行, 与普通 insert 之间不互斥
+    pub fn update_field<K, V, T, A, F>(&self, key: K, accessor: A, f: F) -> Result<bool>
+    where
+        K: Into<String>,
+        V: Serialize + DeserializeOwned + Sync + Send,
+        A: FnOnce(&mut V) -> &mut T,
+        F: FnOnce(&mut T),
+    {
+        let k = key.into();
+        let _guard = self.key_lock(&k);
+        let Some(v) = self.lookup(&k) else {
+            return Ok(false);
+        };
+        let mut value = self
+            .decode::<V>(v.bytes())
+            .map_err(|e| CacheError::Decode(e.to_string()))?;
+        // 已过期但尚未清理的 entry 视为不存在
+        let Some(exp) = v.remaining_exp() else {
+            return Ok(false);
+        };
+        f(accessor(&mut value));
+        let entry = self.rebuild_entry(&v, exp, self.encode(&value)?);
+        self.put(k, entry)?;
+        self.maintain();
+        Ok(true)
+    }
+
+    // 只缩短 ttl: new_exp 比剩余 ttl 短时才用 new_exp 重新计时, 否则返回 Ok(false) 不做修改
+    pub fn downgrade_expiration<K>(&self, key: K, new_exp: Expiration) -> Result<bool>
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        let Some(v) = self.cache().get(&k) else {
+            return Err(anyhow!("key: {} not found", k));
+        };
+        let shorter = match (new_exp.as_duration(), v.remaining()) {
+            (Some(new), Some(remaining)) => new < remaining,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if shorter {
+            self.reset_exp(k, v, new_exp);
+        }
+        Ok(shorter)
+    }
+
+    // 只延长 ttl: new_exp 比剩余 ttl 长时才用 new_exp 重新计时, 否则返回 Ok(false) 不做修改
+    pub fn upgrade_expiration<K>(&self, key: K, new_exp: Expiration) -> Result<bool>
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        let Some(v) = self.cache().get(&k) else {
+            return Err(anyhow!("key: {} not found", k));
+        };
+        let longer = match (new_exp.as_duration(), v.remaining()) {
+            (Some(new), Some(remaining)) => new > remaining,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        if longer {
+            self.reset_exp(k, v, new_exp);
+        }
+        Ok(longer)
+    }
+
+    fn key_lock(&self, k: &str) -> std::sync::MutexGuard<'_, ()> {
+        use std::hash::{BuildHasher, RandomState};
+        static SEED: OnceLock<RandomState> = OnceLock::new();
+        let idx = SEED.get_or_init(RandomState::new).hash_one(k) as usize % KEY_LOCK_STRIPES;
+        self.inner.key_locks[idx]
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 重新计时: 在 moka 的 entry 锁内原地替换, 版本不变, on_evict 等注册随 entry 保留,
+    // 也不调用 callback. entry 已被删除或被重新写入 (版本不同) 时不做修改, 返回 false
+    fn reset_exp(&self, k: String, mut v: CacheEntry, new_exp: Expiration) -> bool {
+        let version = v.version;
+        v.data.0 = new_exp;
+        v.inserted_at = Instant::now();
+        let r = self.cache().entry(k).and_compute_with(|old| match old {
+            Some(old) if old.value().version == version => {
+                self.inner
+                    .state
+                    .silent
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(version);
+                moka::ops::compute::Op::Put(v)
+            }
+            _ => moka::ops::compute::Op::Nop,
+        });
+        matches!(r, moka::ops::compute::CompResult::ReplacedWith(_))
+    }
+
+    // 刷新剩余 ttl 低于原 ttl * threshold_pct 的 entry, 返回刷新的个数;
+    // threshold_pct = 0.2 表示剩余 20% 时刷新
+    pub fn refresh_before_expiry(&self, threshold_pct: f64) -> usize {
+        let mut n = 0;
+        for (k, v) in self.cache().iter() {
+            let (Some(ttl), Some(remaining)) = (v.data.0.as_duration(), v.remaining()) else {
+                continue;
+            };
+            if remaining.is_zero() || remaining.as_secs_f64() >= ttl.as_secs_f64() * threshold_pct {
+                continue;
+            }
+            let exp = v.data.0;
+            if self.reset_exp(k.as_ref().clone(), v, exp) {
+                n += 1;
+            }
+        }
+        n
+    }
+
+    // 遍历所有 entry, 返回遍历的个数; 遍历期间的写入可能看得到也可能看不到
+    pub fn for_each<F>(&self, f: F) -> Result<usize>
+    where
+        F: Fn(&str, &CacheData),
+    {
+        let mut n = 0;
+        for (k, v) in self.cache().iter() {
+            f(&k, &v.into_data());
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    // 反序列化后遍历, 返回 (遍历的个数, 反序列化成功的个数), 失败的 entry 不调用 f
+    pub fn for_each_typed<V, F>(&self, f: F) -> Result<(usize, usize)>
+    where
+        V: DeserializeOwned,
+        F: Fn(&str, V),
+    {
+        let (mut visited, mut decoded) = (0, 0);
+        for (k, v) in self.cache().iter() {
+            visited += 1;
+            if let Ok(value) = self.decode::<V>(v.bytes()) {
+                f(&k, value);
+                decoded += 1;
+            }
+        }
+        Ok((visited, decoded))
+    }
+
+    // 复制全部 entry 的存储字节 (与 bulk_load 的输入格式相同), 用于测试断言和排查时导出状态;
+    // 会复制所有数据, 遍历期间的写入可能看得到也可能看不到, 不是一致的快照
+    pub fn export(&self) -> HashMap<String, CacheData> {
+        self.cache()
+            .iter()
+            .map(|(k, v)| (k.as_ref().clone(), v.into_data()))
+            .collect()
+    }
+
+    // 按 V 反序列化后导出, 无法解析的 entry (包括非 codec 编码的) 跳过; 一致性同 export
+    pub fn export_typed<V>(&self) -> HashMap<String, (Expiration, V)>
+    where
+        V: DeserializeOwned,
+    {
+        self.cache()
+            .iter()
+            .filter(|(_, v)| v.content_type == ContentType::Encoded)
+            .filter_map(|(k, v)| {
+                let value = self.decode::<V>(v.bytes()).ok()?;
+                Some((k.as_ref().clone(), (v.data.0, value)))
+            })
+            .collect()
+    }
+
+    // 检查所有 entry 能否按 V 解析 (例如结构变更之后), 返回 (可解析的个数, 无法解析的 key, 已排序);
+    // 不会删除任何 entry, 之后可以对无法解析的 key 调用 remove 或 migrate.
+    // 非 codec 编码的 entry (insert_json、弱引用) 无法通过 get 读取, 同样计入无法解析
+    pub fn validate_all<V>(&self) -> Result<(usize, Vec<String>)>
+    where
+        V: DeserializeOwned,
+    {
+        let mut valid = 0;
+        let mut invalid = Vec::new();
+        for (k, v) in self.cache().iter() {
+            let ok = v.content_type == ContentType::Encoded && self.decode::<V>(v.bytes()).is_ok();
+            if ok {
+                valid += 1;
+            } else {
+                invalid.push(k.as_ref().clone());
+            }
+        }
+        invalid.sort();
+        Ok((valid, invalid))
+    }
+
+    // 按 value 的内容批量删除: 按 V 解析每个 entry, f 返回 true 的删除, 返回删除的个数;
+    // 无法按 V 解析的 entry 跳过. 需要解析全部 entry, 耗时与缓存大小成正比
+    pub fn invalidate_values_if<V, F>(&self, f: F) -> Result<usize>
+    where
+        V: DeserializeOwned,
+        F: Fn(&V) -> bool,
+    {
+        let mut n = 0;
+        for (k, v) in self.cache().iter() {
+            if v.content_type != ContentType::Encoded {
+                continue;
+            }
+            let Ok(value) = self.decode::<V>(v.bytes()) else {
+                continue;
+            };
+            if f(&value) && self.take(&k).is_some() {
+                n += 1;
+            }
+        }
+        Ok(n)
+    }
+
+    // 按写入时间删除最早的 n 个 entry (FIFO), 不受 moka 淘汰策略影响, 返回实际删除的个数.
+    // 没有单独维护写入顺序的队列, 每次调用都要遍历并排序全部 entry;
+    // 遍历后被重新写入的 key 不会按旧的写入时间删除
+    pub fn remove_oldest(&self, n: usize) -> Result<usize> {
+        let mut entries: Vec<(Instant, Arc<String>)> = self
+            .cache()
+            .iter()
+            .map(|(k, v)| (v.inserted_at, k))
+            .collect();
+        entries.sort();
+        let mut removed = 0;
+        for (at, k) in entries {
+            if removed == n {
+                break;
+            }
+            let r = self
+                .cache()
+                .entry(k.as_ref().clone())
+                .and_compute_with(|old| match old {
+                    Some(e) if e.value().inserted_at == at => moka::ops::compute::Op::Remove,
+                    _ => moka::ops::compute::Op::Nop,
+                });
+            if matches!(r, moka::ops::compute::CompResult::Removed(_)) {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    // 删除全部 entry, 每个 entry 以 RemovalCause::Explicit 调用 callback, 返回前执行维护任务把通知发出
+    pub fn invalidate_all(&self) {
+        self.cache().invalidate_all();
+        self.cache().run_pending_tasks();
+    }
+
+    pub fn entry_count(&self) -> u64 {
+        self.cache().entry_count()
+    }
+
+    // entry_count 的别名
+    pub fn len(&self) -> u64 {
+        self.entry_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entry_count() == 0
+    }
+
+    pub fn weighted_size(&self) -> u64 {
+        self.cache().weighted_size()
+    }
+
+    // key 和 value 的字节数之和
+    fn key_value_bytes(&self) -> usize {
+        self.cache()
+            .iter()
+            .map(|(k, v)| k.len() + v.bytes().len())
+            .sum()
+    }
+
+    // 估算占用的内存: key 和 value 的字节数之和, 加上每个 entry ENTRY_OVERHEAD_BYTES 的结构开销.
+    // 不含 moka 内部的哈希表分段、访问队列、频率统计等分配, 只是下限; 需要遍历全部 entry, 不要频繁调用
+    pub fn estimated_memory_bytes(&self) -> usize {
+        self.cache().run_pending_tasks();
+        let n = usize::try_from(self.entry_count()).unwrap_or(usize::MAX);
+        self.key_value_bytes()
+            .saturating_add(n.saturating_mul(ENTRY_OVERHEAD_BYTES))
+    }
+
+    // 汇总 stats、容量和 entry 信息; 需要遍历全部 entry 计算字节数, 不要频繁调用
+    pub fn telemetry_report(&self) -> TelemetryReport {
+        self.cache().run_pending_tasks();
+        let stats = &self.inner.state.stats;
+        let evictions_by_cause = [
+            RemovalCause::Expired,
+            RemovalCause::Explicit,
+            RemovalCause::Replaced,
+            RemovalCause::Size,
+        ]
+        .into_iter()
+        .map(|c| (format!("{:?}", c), stats.removals(c)))
+        .collect();
+        let estimated_size_bytes = self.key_value_bytes() as u64;
+        let mut top: Vec<(String, u64)> = self
+            .inner
+            .state
+            .access_counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(k, n)| (k.clone(), *n))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(10);
+        TelemetryReport {
+            hits: stats.hits(),
+            misses: stats.misses(),
+            inserts: stats.inserts(),
+            evictions_by_cause,
+            entry_count: self.entry_count(),
+            capacity: self.capacity(),
+            estimated_size_bytes,
+            overhead_per_entry: ENTRY_OVERHEAD_BYTES as u64,
+            lifetime_histogram: stats.lifetime_histogram(),
+            top_10_keys_by_access: top,
+        }
+    }
+
+    // 当前的容量上限 (max_capacity), set_max_capacity 后返回新值
+    pub fn get_max_capacity(&self) -> Option<u64> {
+        self.cache().policy().max_capacity()
+    }
+
+    // 运行中调整容量. moka 0.12 不支持修改已创建缓存的容量 (Policy::set_max_capacity 不公开),
+    // 这里按新容量重建缓存并迁移现有 entry, 剩余 ttl 和属性保持不变, 不计入 inserts 统计;
+    // 新容量不足时超出的 entry 在维护时按淘汰策略驱逐 (RemovalCause::Size, 触发淘汰回调).
+    // 迁移期间的读写会等待; 已经在进行中的写入可能落到旧缓存而丢失, 应在低峰期调用
+    pub fn set_max_capacity(&self, new_cap: u64) -> Result<()> {
+        if new_cap == 0 {
+            return Err(anyhow!("max capacity must be greater than 0"));
+        }
+        let mut cache = self.inner.cache.write().unwrap_or_else(|e| e.into_inner());
+        let config = CacheConfig {
+            max_capacity: new_cap,
+            ..self.inner.config.clone()
+        };
+        // 旧缓存直接丢弃不会触发淘汰, 原始权重由迁移时的 weigher 重新累计
+        self.inner.state.raw_weight.store(0, Ordering::Relaxed);
+        let resized = build_cache(config, self.inner.state.clone());
+        for (k, v) in cache.iter() {
+            resized.insert(k.as_ref().clone(), v);
+        }
+        *cache = resized;
+        drop(cache);
+        self.cache().run_pending_tasks();
+        Ok(())
+    }
+
+    // 配置的容量: 有 weigher 时是权重上限, 否则是 entry 个数上限
+    pub fn capacity(&self) -> u64 {
+        self.cache()
+            .policy()
+            .max_capacity()
+            .unwrap_or(self.inner.config.max_capacity)
+    }
+
+    // 剩余容量 = capacity - weighted_size
+    pub fn headroom(&self) -> u64 {
+        self.capacity().saturating_sub(self.weighted_size())
+    }
+
+    // 按 key 拆分为两个新缓存: (f 返回 true 的, f 返回 false 的)
+    // 两个新缓存沿用原配置 (容量、回调、weigher), 原缓存被消费;
+    // 迁移的 entry 保留剩余 ttl, 计入新缓存的 inserts 统计. insert_with_on_evict、insert_indexed、
+    // mark_refreshing 等注册随 entry 转到它所在的新缓存; with_bounded_channel 的事件 channel 不会转移
+    pub fn partition_by<F>(self, f: F) -> (CacheClient, CacheClient)
+    where
+        F: Fn(&str) -> bool,
+    {
+        let left = CacheClient::new(self.inner.config.clone());
+        let right = CacheClient::new(self.inner.config.clone());
+        for (k, v) in self.cache().iter() {
+            let dest = if f(&k) { &left } else { &right };
+            let registrations = self.inner.state.detach(&k, v.version);
+            // 配置相同, key 长度在写入原缓存时已检查过
+            let _ = dest.put(k.as_ref().clone(), v);
+            dest.inner.state.attach(&k, registrations);
+        }
+        left.maintain();
+        right.maintain();
+        (left, right)
+    }
+
+    // 合并两个缓存为新缓存, 两个原缓存被消费. 新缓存只使用 self 的配置 (callback、weigher、max_key_len、
+    // codec 等), 容量为两者之和, other 的配置被忽略: 两边的 codec 等需要一致, other 中超过 self 的
+    // max_key_len 的 key 记录日志后跳过. key 冲突时按 conflict 选择, TakeOldest/TakeNewest 按写入顺序比较
+    // (entry 版本, refresh 等重新计时不改变). 和 partition_by 一样, 迁移的 entry 保留剩余 ttl,
+    // 两边的 on_evict、索引等注册随 entry 转到新缓存; 没有进入新缓存的 entry 的 on_evict 按 Replaced 调用
+    pub fn union(self, other: CacheClient, conflict: ConflictResolution) -> CacheClient {
+        let config = CacheConfig {
+            max_capacity: self.capacity().saturating_add(other.capacity()),
+            ..self.inner.config.clone()
+        };
+        let merged = CacheClient::new(config);
+        for (k, v) in self.cache().iter() {
+            let registrations = self.inner.state.detach(&k, v.version);
+            // 沿用 self 的配置, key 长度在写入 self 时已检查过
+            let _ = merged.put(k.as_ref().clone(), v);
+            merged.inner.state.attach(&k, registrations);
+        }
+        for (k, v) in other.cache().iter() {
+            let take = match merged.cache().get(k.as_ref()) {
+                None => true,
+                Some(old) => match conflict {
+                    ConflictResolution::TakeLeft => false,
+                    ConflictResolution::TakeRight => true,
+                    ConflictResolution::TakeOldest => v.version < old.version,
+                    ConflictResolution::TakeNewest => v.version > old.version,
+                },
+            };
+            let registrations = other.inner.state.detach(&k, v.version);
+            if !take || merged.put(k.as_ref().clone(), v).is_err() {
+                for (_, f) in registrations.callbacks {
+                    f(RemovalCause::Replaced);
+                }
+                continue;
+            }
+            merged.inner.state.attach(&k, registrations);
+        }
+        merged.maintain();
+        merged
+    }
+}
+
+static CacheHand: OnceLock<CacheClient> = OnceLock::new();
+
+//初始化缓存
+pub fn setup(
+    callback: Option<fn(Arc<String>, CacheData, RemovalCause)>,
+    max_cap: u64,
+) -> Result<()> {
+    setup_with_config(CacheConfig {
+        max_capacity: max_cap,
+        callback,
+        weigher: None,
+        ..Default::default()
+    })
+}
+
+// 与 setup 相同, 但已初始化时返回 Ok(false) 而不是 Err (本次的参数被忽略);
+// 供封装本 crate 的库在自己的初始化中调用, 这类调用方无法控制初始化顺序
+pub fn try_setup(
+    callback: Option<fn(Arc<String>, CacheData, RemovalCause)>,
+    max_cap: u64,
+) -> Result<bool> {
+    let mut initialized = false;
+    CacheHand.get_or_init(|| {
+        initialized = true;
+        CacheClient::new(CacheConfig {
+            max_capacity: max_cap,
+            callback,
+            weigher: None,
+            ..Default::default()
+        })
+    });
+    Ok(initialized)
+}
+
+//按配置初始化缓存
+pub fn setup_with_config(config: CacheConfig) -> Result<()> {
+    CacheHand
+        .set(CacheClient::new(config))
+        .map_err(|_| anyhow!("setup cache error: already initialized"))?;
+    Ok(())
+}
+
+// 全局缓存的句柄: 热路径上取一次保存下来, 之后直接调用 CacheClient 的方法,
+// 省去每次调用全局函数时的 OnceLock::get 和判空; 未初始化返回 None
+pub fn handle() -> Option<&'static CacheClient> {
+    CacheHand.get()
+}
+
+// 初始化缓存并写入初始数据
+pub fn setup_with_initial_entries<K, V, I>(entries: I, exp: Expiration, max_cap: u64) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+    I: IntoIterator<Item = (K, V)>,
+{
+    setup(None, max_cap)?;
+    extend(entries, exp)
+}
+
+// 按配置初始化缓存, 并返回容量为 cap 的淘汰事件 channel
+#[cfg(feature = "async")]
+pub fn setup_with_bounded_channel(
+    config: CacheConfig,
+    cap: usize,
+    policy: ChannelFullPolicy,
+) -> Result<tokio::sync::mpsc::Receiver<EvictionEvent>> {
+    let (client, rx) = CacheClient::with_bounded_channel(config, cap, policy);
+    CacheHand
+        .set(client)
+        .map_err(|_| anyhow!("setup cache error: already initialized"))?;
+    Ok(rx)
+}
+
+#[cfg(feature = "async")]
+pub fn dropped_events() -> u64 {
+    CacheHand.get().map(|h| h.dropped_events()).unwrap_or(0)
+}
+
+#[cfg(feature = "async")]
+pub fn pending_events() -> usize {
+    CacheHand.get().map(|h| h.pending_events()).unwrap_or(0)
+}
+
+#[cfg(feature = "access-log")]
+pub fn recent_accesses(n: usize) -> Vec<(String, Instant)> {
+    CacheHand
+        .get()
+        .map(|h| h.recent_accesses(n))
+        .unwrap_or_default()
+}
+
+pub fn stats() -> Option<&'static CacheStats> {
+    Some(CacheHand.get()?.stats())
+}
+
+// entry 个数 (近似值, 未执行的维护任务不会计入)
+pub fn entry_count() -> u64 {
+    CacheHand.get().map(|h| h.entry_count()).unwrap_or(0)
+}
+
+// entry_count 的别名, 同样是近似值
+pub fn len() -> u64 {
+    entry_count()
+}
+
+pub fn is_empty() -> bool {
+    entry_count() == 0
+}
+
+// 权重总和, 未配置 weigher 时等于 entry 个数 (近似值)
+pub fn weighted_size() -> u64 {
+    CacheHand.get().map(|h| h.weighted_size()).unwrap_or(0)
+}
+
+pub fn estimated_memory_bytes() -> Option<usize> {
+    CacheHand.get().map(|h| h.estimated_memory_bytes())
+}
+
+pub fn telemetry_report() -> Option<TelemetryReport> {
+    CacheHand.get().map(|h| h.telemetry_report())
+}
+
+pub fn get_max_capacity() -> Option<u64> {
+    CacheHand.get()?.get_max_capacity()
+}
+
+pub fn set_max_capacity(new_cap: u64) -> Result<()> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.set_max_capacity(new_cap)
+}
+
+pub fn capacity() -> u64 {
+    CacheHand.get().map(|h| h.capacity()).unwrap_or(0)
+}
+
+pub fn headroom() -> u64 {
+    CacheHand.get().map(|h| h.headroom()).unwrap_or(0)
+}
+
+// pub fn insert<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
+// where
+//     K: Into<String>,
+//     V: Serialize + Encode + Sync + Send,
+// {
+//     let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+//     let k = key.into();
+//     let b = bincode::encode_to_vec(&value, config::standard())?;
+//     cache.insert(k, (exp, b));
+//     Ok(())
+// }
+
+pub fn insert<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert(key, value, exp)
+}
+
+pub fn insert_or_extend_ttl<K, V>(key: K, value: V, exp: Expiration) -> Result<InsertResult>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_or_extend_ttl(key, value, exp)
+}
+
+pub fn insert_if_absent<K, V>(key: K, value: V, exp: Expiration) -> Result<bool>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_if_absent(key, value, exp)
+}
+
+pub fn multi_set_if_absent<K, V, I>(entries: I, exp: Expiration) -> Result<usize>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.multi_set_if_absent(entries, exp)
+}
+
+pub fn try_insert_without_evicting<K, V>(key: K, value: V, exp: Expiration) -> Result<bool>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.try_insert_without_evicting(key, value, exp)
+}
+
+pub fn extend<K, V, I>(entries: I, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+    I: IntoIterator<Item = (K, V)>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.extend(entries, exp)
+}
+
+pub fn insert_many_with_individual_exp<K, V, I>(entries: I) -> Result<usize>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+    I: IntoIterator<Item = (K, V, Expiration)>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_many_with_individual_exp(entries)
+}
+
+pub fn insert_all_or_none(entries: Vec<(String, CacheData)>) -> Result<()> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_all_or_none(entries)
+}
+
+pub fn bulk_load(entries: Vec<(String, CacheData)>, replace_existing: bool) -> Result<usize> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    Ok(cache.bulk_load(entries, replace_existing))
+}
+
+pub fn freeze() {
+    if let Some(cache) = CacheHand.get() {
+        cache.freeze();
+    }
+}
+
+pub fn thaw() {
+    if let Some(cache) = CacheHand.get() {
+        cache.thaw();
+    }
+}
+
+// 把全局缓存复制到 dest
+pub fn copy_all_to(dest: &CacheClient) -> Result<usize> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.copy_all_to(dest)
+}
+
+pub fn rekey_all<F>(f: F) -> Result<usize>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.rekey_all(f)
+}
+
+pub fn insert_indexed<K, V, F>(key: K, value: V, exp: Expiration, index_fn: F) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+    F: FnOnce(&V) -> Vec<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_indexed(key, value, exp, index_fn)
+}
+
+pub fn insert_with_on_evict<K, V, F>(key: K, value: V, exp: Expiration, on_evict: F) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+    F: FnOnce(RemovalCause) + Send + 'static,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_with_on_evict(key, value, exp, on_evict)
+}
+
+pub fn insert_with_weak_reference<K, V>(key: K, value: Arc<V>, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+    V: Send + Sync + 'static,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_with_weak_reference(key, value, exp)
+}
+
+pub fn get_weak<K, V>(key: K) -> Option<Arc<V>>
+where
+    K: Into<String>,
+    V: Send + Sync + 'static,
+{
+    CacheHand.get()?.get_weak(key)
+}
+
+pub fn get_by_index<V>(index_value: &str) -> Option<(Expiration, V)>
+where
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand.get()?.get_by_index(index_value)
+}
+
+pub fn insert_duration<K, V>(key: K, value: V, duration: Duration) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_duration(key, value, duration)
+}
+
+pub fn try_insert<K, V>(key: K, value: V, exp: Expiration) -> Result<bool>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.try_insert(key, value, exp)
+}
+
+pub fn insert_ephemeral<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_ephemeral(key, value, exp)
+}
+
+pub fn is_ephemeral(key: &str) -> bool {
+    CacheHand.get().is_some_and(|h| h.is_ephemeral(key))
+}
+
+pub fn insert_with_checksum<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+    V: Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_with_checksum(key, value, exp)
+}
+
+pub fn get<K, V>(key: K) -> Option<(Expiration, V)>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand.get()?.get(key)
+}
+
+pub fn try_get<K, V>(key: K) -> Result<Option<(Expiration, V)>, CacheError>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand
+        .get()
+        .ok_or(CacheError::NotInitialized)?
+        .try_get(key)
+}
+
+pub fn get_deserialized_or_raw<K, V>(key: K) -> Option<Either<V, (DecodeError, Vec<u8>)>>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand.get()?.get_deserialized_or_raw(key)
+}
+
+// cache 未初始化时直接解析, 不缓存
+pub fn get_or_parse<V, E, F>(
+    source: &str,
+    exp: Expiration,
+    f: F,
+) -> std::result::Result<(Expiration, V), E>
+where
+    V: Serialize + DeserializeOwned + Sync + Send,
+    F: FnOnce(&str) -> std::result::Result<V, E>,
+{
+    match CacheHand.get() {
+        Some(cache) => cache.get_or_parse(source, exp, f),
+        None => f(source).map(|v| (exp, v)),
+    }
+}
+
+pub fn get_or_insert_with_exp<K, V, F>(key: K, f: F) -> Result<(Expiration, V)>
+where
+    K: Into<String>,
+    V: Serialize + DeserializeOwned + Sync + Send,
+    F: FnOnce() -> (V, Expiration),
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.get_or_insert_with_exp(key, f)
+}
+
+pub fn get_or_insert_try_with<K, V, F>(key: K, exp: Expiration, loader: F) -> Result<V>
+where
+    K: Into<String>,
+    V: Serialize + DeserializeOwned + Sync + Send,
+    F: FnOnce() -> Result<V>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.get_or_insert_try_with(key, exp, loader)
+}
+
+pub fn get_or_default<K, V>(key: K, exp: Expiration) -> Result<V>
+where
+    K: Into<String>,
+    V: Default + Serialize + DeserializeOwned + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.get_or_default(key, exp)
+}
+
+pub fn get_or_insert_with_retry<K, V, F>(
+    key: K,
+    exp: Expiration,
+    loader: F,
+    max_retries: u32,
+    backoff: Duration,
+) -> Result<V>
+where
+    K: Into<String>,
+    V: Serialize + DeserializeOwned + Sync + Send,
+    F: FnMut() -> Result<V>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.get_or_insert_with_retry(key, exp, loader, max_retries, backoff)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_or_insert_with_retry_async<K, V, F, Fut>(
+    key: K,
+    exp: Expiration,
+    loader: F,
+    max_retries: u32,
+    backoff: Duration,
+) -> Result<V>
+where
+    K: Into<String>,
+    V: Serialize + DeserializeOwned + Sync + Send,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<V>>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache
+        .get_or_insert_with_retry_async(key, exp, loader, max_retries, backoff)
+        .await
+}
+
+pub fn migrate<K, VOld, VNew, F>(key: K, migration_fn: F) -> Result<Option<VNew>>
+where
+    K: Into<String>,
+    VOld: DeserializeOwned + Sync + Send,
+    VNew: Serialize + DeserializeOwned + Sync + Send,
+    F: FnOnce(VOld) -> Result<VNew>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.migrate(key, migration_fn)
+}
+
+pub fn with_value<K, V, R, F>(key: K, f: F) -> Option<R>
+where
+    K: Into<String>,
+    V: DeserializeOwned,
+    F: FnOnce(&V) -> R,
+{
+    CacheHand.get()?.with_value(key, f)
+}
+
+pub fn get_peek<K, V>(key: K) -> Option<V>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand.get()?.get_peek(key)
+}
+
+pub fn get_many_typed_map<V>(keys: &[&str]) -> HashMap<String, V>
+where
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand
+        .get()
+        .map(|h| h.get_many_typed_map(keys))
+        .unwrap_or_default()
+}
+
+pub fn get_consistent<V>(keys: &[&str]) -> Option<Vec<(Expiration, V)>>
+where
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand.get()?.get_consistent(keys)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn try_get_batch<V>(keys: &[&str]) -> Vec<(String, Result<Option<(Expiration, V)>, CacheError>)>
+where
+    V: DeserializeOwned + Sync + Send,
+{
+    match CacheHand.get() {
+        Some(h) => h.try_get_batch(keys),
+        None => keys
+            .iter()
+            .map(|k| (k.to_string(), Err(CacheError::NotInitialized)))
+            .collect(),
+    }
+}
+
+pub fn deserialize<V>(d: &[u8]) -> Option<V>
+where
+    V: DeserializeOwned + Sync + Send,
+{
+    let c = config::standard();
+    let b = bincode::serde::decode_from_slice::<V, _>(d, c);
+    if let Ok((value, _)) = b {
+        return Some(value);
+    }
+    if let Err(e) = b {
+        log::error!("deserialize error: {}", e);
+    }
+    None
+}
+
+// pub fn get<K, V>(key: K) -> Option<(Expiration, V)>
+// where
+//     K: Into<String>,
+//     V: DeserializeOwned + Decode<()> + Sync + Send,
+// {
+//     if let Some(h) = CacheHand.get() {
+//         let k = key.into();
+
+//         let v = h.get(&k)?;
+
+//         let c = config::standard();
+//         let b = bincode::decode_from_slice::<V, _>(v.1.as_ref(), c);
+//         if let Ok((value, _)) = b {
+//             return Some((v.0, value));
+//         }
+//         if let Err(e) = b {
+//             log::error!("cache deserialize error: {}", e);
+//         }
+//         return None;
+//     }
+
+//     None
+// }
+
+pub fn get_exp<K>(key: K) -> Option<Expiration>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_exp(key)
+}
+
+pub fn mark_refreshing(key: &str) -> bool {
+    CacheHand.get().is_some_and(|h| h.mark_refreshing(key))
+}
+
+pub fn is_refreshing(key: &str) -> bool {
+    CacheHand.get().is_some_and(|h| h.is_refreshing(key))
+}
+
+pub fn clear_refreshing(key: &str) {
+    if let Some(cache) = CacheHand.get() {
+        cache.clear_refreshing(key);
+    }
+}
+
+// 未初始化时同样返回 default
+pub fn get_exp_or_default<K>(key: K, default: Expiration) -> Expiration
+where
+    K: Into<String>,
+{
+    get_exp(key).unwrap_or(default)
+}
+
+pub fn get_exp_or_else<K, F>(key: K, f: F) -> Expiration
+where
+    K: Into<String>,
+    F: FnOnce() -> Expiration,
+{
+    get_exp(key).unwrap_or_else(f)
+}
+
+pub fn get_expiration_kind(key: &str) -> Option<ExpirationKind> {
+    CacheHand.get()?.get_expiration_kind(key)
+}
+
+pub fn is_permanent(key: &str) -> bool {
+    CacheHand.get().is_some_and(|h| h.is_permanent(key))
+}
+
+pub fn is_timed(key: &str) -> bool {
+    CacheHand.get().is_some_and(|h| h.is_timed(key))
+}
+
+#[cfg(feature = "bytes")]
+pub fn insert_bytes_shared<K>(key: K, data: Bytes, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_bytes_shared(key, data, exp);
+    Ok(())
+}
+
+#[cfg(feature = "bytes")]
+pub fn get_bytes_shared<K>(key: K) -> Option<(Expiration, Bytes)>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_bytes_shared(key)
+}
+
+pub fn insert_bytes<K>(key: K, data: Vec<u8>, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_bytes(key, data, exp);
+    Ok(())
+}
+
+pub fn get_bytes<K>(key: K) -> Option<(Expiration, Vec<u8>)>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_bytes(key)
+}
+
+pub fn insert_chunked<K>(key: K, data: &[u8], exp: Expiration, chunk_size: usize) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_chunked(key, data, exp, chunk_size)
+}
+
+pub fn get_chunked<K>(key: K) -> Option<Vec<u8>>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_chunked(key)
+}
+
+#[cfg(feature = "json")]
+pub fn insert_batch_from_json_file(path: &std::path::Path, exp: Expiration) -> Result<usize> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_batch_from_json_file(path, exp)
+}
+
+#[cfg(feature = "json")]
+pub fn export_to_json_file(path: &std::path::Path) -> Result<usize> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.export_to_json_file(path)
+}
+
+pub fn insert_json<K>(key: K, json: &str, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_json(key, json, exp)
+}
+
+pub fn get_json<K>(key: K) -> Option<String>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_json(key)
+}
+
+pub fn insert_tagged<K, V>(key: K, value: V, exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+    V: CacheTag + Serialize + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_tagged(key, value, exp)
+}
+
+pub fn get_tagged<K>(key: K) -> Option<(Expiration, TaggedValue)>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_tagged(key)
+}
+
+pub fn insert_bytes_with_type_tag<K>(
+    key: K,
+    type_name: &str,
+    data: Vec<u8>,
+    exp: Expiration,
+) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.insert_bytes_with_type_tag(key, type_name, data, exp);
+    Ok(())
+}
+
+pub fn get_bytes_with_type_tag<K>(key: K) -> Option<(String, Vec<u8>, Expiration)>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_bytes_with_type_tag(key)
+}
+
+pub fn get_framed<K>(key: K) -> Option<(Expiration, Vec<u8>)>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_framed(key)
+}
+
+pub fn time_to_live<K>(key: K) -> Option<Option<Duration>>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.time_to_live(key)
+}
+
+pub fn probe<K>(key: K) -> Option<(Expiration, usize)>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.probe(key)
+}
+
+pub fn get_bytes_len<K>(key: K) -> Option<usize>
+where
+    K: Into<String>,
+{
+    CacheHand.get()?.get_bytes_len(key)
+}
+
+pub fn remove<K>(key: K)
+where
+    K: Into<String>,
+{
+    if let Some(h) = CacheHand.get() {
+        h.remove(key);
+    }
+}
+
+pub fn remove_many<K>(keys: &[K]) -> usize
+where
+    K: AsRef<str>,
+{
+    CacheHand.get().map(|h| h.remove_many(keys)).unwrap_or(0)
+}
+
+pub fn remove_many_detailed<K>(keys: &[K]) -> Vec<(String, Expiration)>
+where
+    K: AsRef<str>,
+{
+    CacheHand
+        .get()
+        .map(|h| h.remove_many_detailed(keys))
+        .unwrap_or_default()
+}
+
+pub fn remove_typed<K, V>(key: K) -> Option<(Expiration, V)>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand.get()?.remove_typed(key)
+}
+
+pub fn try_remove_typed<K, V>(key: K) -> Result<Option<(Expiration, V)>, CacheError>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    CacheHand
+        .get()
+        .ok_or(CacheError::NotInitialized)?
+        .try_remove_typed(key)
+}
+
+pub fn contains_key<K>(key: K) -> bool
+where
+    K: Into<String>,
+{
+    CacheHand
+        .get()
+        .map(|h| h.contains_key(key))
+        .unwrap_or(false)
+}
+
+//每隔10检查缓存是否过期
+pub fn check_exp_interval() {
+    if let Some(cache) = CacheHand.get() {
+        cache.check_exp_interval();
+    }
+}
+
+// 刷新key ttl
+pub fn refresh<K>(key: K) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.refresh(key)
+}
+
+pub fn refresh_all() -> Result<usize> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.refresh_all()
+}
+
+pub fn expire_after_read_reset<K>(key: K, new_exp: Expiration) -> Result<()>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.expire_after_read_reset(key, new_exp)
+}
+
+pub fn update_field<K, V, T, A, F>(key: K, accessor: A, f: F) -> Result<bool>
+where
+    K: Into<String>,
+    V: Serialize + DeserializeOwned + Sync + Send,
+    A: FnOnce(&mut V) -> &mut T,
+    F: FnOnce(&mut T),
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.update_field(key, accessor, f)
+}
+
+pub fn get_and_extend<K, V>(key: K, extension: Duration) -> Result<Option<V>>
+where
+    K: Into<String>,
+    V: DeserializeOwned + Sync + Send,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.get_and_extend(key, extension)
+}
+
+pub fn downgrade_expiration<K>(key: K, new_exp: Expiration) -> Result<bool>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.downgrade_expiration(key, new_exp)
+}
+
+pub fn upgrade_expiration<K>(key: K, new_exp: Expiration) -> Result<bool>
+where
+    K: Into<String>,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.upgrade_expiration(key, new_exp)
+}
+
+pub fn for_each<F>(f: F) -> Result<usize>
+where
+    F: Fn(&str, &CacheData),
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.for_each(f)
+}
+
+pub fn export() -> Result<HashMap<String, CacheData>> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    Ok(cache.export())
+}
+
+pub fn export_typed<V>() -> Result<HashMap<String, (Expiration, V)>>
+where
+    V: DeserializeOwned,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    Ok(cache.export_typed())
+}
+
+pub fn for_each_typed<V, F>(f: F) -> Result<(usize, usize)>
+where
+    V: DeserializeOwned,
+    F: Fn(&str, V),
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.for_each_typed(f)
+}
+
+pub fn validate_all<V>() -> Result<(usize, Vec<String>)>
+where
+    V: DeserializeOwned,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.validate_all::<V>()
+}
+
+pub fn invalidate_values_if<V, F>(f: F) -> Result<usize>
+where
+    V: DeserializeOwned,
+    F: Fn(&V) -> bool,
+{
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.invalidate_values_if(f)
+}
+
+pub fn remove_oldest(n: usize) -> Result<usize> {
+    let cache = CacheHand.get().ok_or_else(|| anyhow!("cache is null"))?;
+    cache.remove_oldest(n)
+}
+
+pub fn refresh_before_expiry(threshold_pct: f64) -> usize {
+    CacheHand
+        .get()
+        .map(|h| h.refresh_before_expiry(threshold_pct))
+        .unwrap_or(0)
+}
+
+// 后台任务: 每隔 interval 执行一次 refresh_before_expiry, 需要在 tokio runtime 中调用
+#[cfg(feature = "async")]
+pub fn auto_refresh_before_expiry(
+    threshold_pct: f64,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let n = refresh_before_expiry(threshold_pct);
+            log::info!("auto refresh before expiry, refreshed: {}", n);
+        }
+    })
+}
+
+static EXP_TASK_STOP: AtomicBool = AtomicBool::new(false);
+// 运行中的 check_exp_interval_every 线程数, 以及用于提前唤醒它们的句柄
+static EXP_TASK_RUNNING: AtomicU32 = AtomicU32::new(0);
+static EXP_TASK_THREADS: Mutex<Vec<std::thread::Thread>> = Mutex::new(Vec::new());
+
+// 后台线程: 每隔 secs 秒执行一次 check_exp_interval, 直到调用 stop_exp_interval_task 或 shutdown;
+// 所有任务共用同一个停止标志
+pub fn check_exp_interval_every(secs: u64) -> std::thread::JoinHandle<()> {
+    EXP_TASK_STOP.store(false, Ordering::SeqCst);
+    EXP_TASK_RUNNING.fetch_add(1, Ordering::SeqCst);
+    std::thread::spawn(move || {
+        // 先登记再检查停止标志, 保证 stop 时要么能唤醒本线程, 要么本线程能看到标志
+        EXP_TASK_THREADS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(std::thread::current());
+        'task: loop {
+            let deadline = Instant::now() + Duration::from_secs(secs);
+            loop {
+                if EXP_TASK_STOP.load(Ordering::SeqCst) {
+                    break 'task;
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                std::thread::park_timeout(deadline - now);
+            }
+            check_exp_interval();
+        }
+        EXP_TASK_RUNNING.fetch_sub(1, Ordering::SeqCst);
+    })
+}
+
+pub fn stop_exp_interval_task() {
+    EXP_TASK_STOP.store(true, Ordering::SeqCst);
+    let threads = std::mem::take(&mut *EXP_TASK_THREADS.lock().unwrap_or_else(|e| e.into_inner()));
+    for t in threads {
+        t.unpark();
+    }
+}
+
+// 关闭: 停止并等待 check_exp_interval_every 线程退出, 然后执行积压的维护任务,
+// 已过期/已驱逐 entry 的 callback 和 async 通道事件在返回前发出. 可重复调用, 未 setup 时也可调用
+pub fn shutdown() {
+    stop_exp_interval_task();
+    while EXP_TASK_RUNNING.load(Ordering::SeqCst) > 0 {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    if let Some(cache) = CacheHand.get() {
+        cache.check_exp_interval();
+    }
+}
+
+pub fn invalidate_all() {
+    if let Some(cache) = CacheHand.get() {
+        cache.invalidate_all();
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod test {
+
+    use super::*;
+    use std::thread::sleep;
+
+    fn cache_key_expired(key: Arc<String>, value: CacheData, cause: RemovalCause) {
+        println!("过期 key-----> {key}. value--> {value:?}. Cause: {cause:?}");
+    }
+    fn init() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| setup(Some(cache_key_expired), 512).unwrap());
+    }
+
+    #[test]
+    fn test_encode_decode() {
         let value: i32 = 1000;
         let config = config::standard().with_little_endian();
-        let b = bincode::encode_to_vec(&value, config).unwrap();
+        let b = bincode::encode_to_vec(value, config).unwrap();
         println!("b-->{:?}", b);
         let (value, _) = bincode::decode_from_slice::<i32, _>(b.as_ref(), config).unwrap();
         println!("value-->{}", value);
     }
 
+    #[test]
+    fn test_cache_weigher() {
+        let config = CacheConfig::new(1024).with_weigher(|k, v| (k.len() + v.1.len() + 8) as u32);
+        let cache = build_cache(config, Default::default());
+        cache.insert(
+            "abc".to_string(),
+            CacheEntry::new(Expiration::Never, vec![0; 10]),
+        );
+        cache.insert(
+            "de".to_string(),
+            CacheEntry::new(Expiration::Never, vec![0; 20]),
+        );
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), (3 + 10 + 8) + (2 + 20 + 8));
+
+        let cache = build_cache(CacheConfig::new(1024), Default::default());
+        cache.insert(
+            "abc".to_string(),
+            CacheEntry::new(Expiration::Never, vec![0; 10]),
+        );
+        cache.run_pending_tasks();
+        assert_eq!(cache.weighted_size(), 10);
+    }
+
+    #[test]
+    fn test_cache_partition_by() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("user:1", 1, Expiration::Never).unwrap();
+        client.insert("user:2", 2, Expiration::Never).unwrap();
+        client.insert("order:1", 3, Expiration::Second(60)).unwrap();
+
+        let (users, others) = client.partition_by(|k| k.starts_with("user:"));
+        assert_eq!(users.get::<_, i32>("user:1"), Some((Expiration::Never, 1)));
+        assert_eq!(users.get::<_, i32>("user:2"), Some((Expiration::Never, 2)));
+        assert!(!users.contains_key("order:1"));
+        assert_eq!(
+            others.get::<_, i32>("order:1"),
+            Some((Expiration::Second(60), 3))
+        );
+        assert!(!others.contains_key("user:1"));
+        assert_eq!(others.stats().inserts(), 1);
+    }
+
+    #[test]
+    fn test_cache_partition_by_keeps_registrations() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert_indexed("user:1", 1u32, Expiration::Never, |_| {
+                vec!["a@x.com".to_string()]
+            })
+            .unwrap();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let e = evicted.clone();
+        client
+            .insert_with_on_evict("order:1", 2u32, Expiration::Never, move |cause| {
+                e.lock().unwrap().push(cause)
+            })
+            .unwrap();
+
+        let (users, orders) = client.partition_by(|k| k.starts_with("user:"));
+        assert_eq!(
+            users.get_by_index::<u32>("a@x.com"),
+            Some((Expiration::Never, 1))
+        );
+        assert_eq!(orders.get_by_index::<u32>("a@x.com"), None);
+        assert!(evicted.lock().unwrap().is_empty());
+        orders.remove("order:1");
+        assert_eq!(*evicted.lock().unwrap(), vec![RemovalCause::Explicit]);
+    }
+
+    #[test]
+    fn test_cache_partition_by_keeps_deadline() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1, Expiration::Millis(300)).unwrap();
+        sleep(Duration::from_millis(200));
+        let (left, _) = client.partition_by(|_| true);
+        assert!(left.contains_key("a"));
+        sleep(Duration::from_millis(150));
+        left.check_exp_interval();
+        assert!(!left.contains_key("a"));
+    }
+
+    #[test]
+    fn test_cache_opportunistic_maintenance() {
+        let config = CacheConfig {
+            opportunistic_maintenance: 2,
+            ..CacheConfig::new(1024)
+        };
+        let client = CacheClient::new(config);
+        client.insert("a", 1, Expiration::Never).unwrap();
+        client.insert("b", 2, Expiration::Never).unwrap();
+        assert_eq!(client.entry_count(), 2);
+    }
+
+    #[test]
+    fn test_cache_get_bytes_len() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("bytes_len", b"hello".to_vec(), Expiration::Never)
+            .unwrap();
+        // bincode 的长度前缀 1 字节 + 5 字节内容
+        assert_eq!(client.get_bytes_len("bytes_len"), Some(6));
+        assert_eq!(client.get_bytes_len("bytes_len_none"), None);
+    }
+
+    #[test]
+    fn test_cache_try_get_batch() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("batch_ok", 7u32, Expiration::Never).unwrap();
+        client.insert("batch_bad", true, Expiration::Never).unwrap();
+
+        let r = client.try_get_batch::<String>(&["batch_ok", "batch_bad", "batch_miss"]);
+        assert_eq!(r.len(), 3);
+        assert_eq!(r[0].0, "batch_ok");
+        // bool 只有 1 字节, 按 String 读取时长度不够
+        assert!(matches!(r[1].1, Err(CacheError::Truncated(_))));
+        assert_eq!(r[2].1, Ok(None));
+
+        let r = client.try_get_batch::<u32>(&["batch_ok"]);
+        assert_eq!(r[0].1, Ok(Some((Expiration::Never, 7))));
+    }
+
+    #[test]
+    fn test_ttl() {
+        assert!(Ttl::NEVER.is_never());
+        assert_eq!(Ttl::from(Expiration::Never), Ttl::NEVER);
+        assert_eq!(Ttl::from(Expiration::Minute(2)), Ttl::seconds(120));
+        assert_eq!(Expiration::from(Ttl::hours(1)), Expiration::Second(3600));
+        assert_eq!(
+            Expiration::from(Ttl::millis(1500)),
+            Expiration::Millis(1500)
+        );
+        assert_eq!(Expiration::from(Ttl::NEVER), Expiration::Never);
+        // 零时长与永不过期不同
+        assert_eq!(Ttl::new(Duration::ZERO).as_duration(), Some(Duration::ZERO));
+        assert_eq!(
+            Expiration::from(Ttl::new(Duration::ZERO)),
+            Expiration::Second(0)
+        );
+    }
+
+    #[test]
+    fn test_cache_min_write_interval() {
+        let config = CacheConfig {
+            min_write_interval: Some(Duration::from_millis(50)),
+            ..CacheConfig::new(1024)
+        };
+        let client = CacheClient::new(config);
+        assert!(client.try_insert("cooldown", 1, Expiration::Never).unwrap());
+        assert!(!client.try_insert("cooldown", 2, Expiration::Never).unwrap());
+        assert_eq!(
+            client.get::<_, i32>("cooldown"),
+            Some((Expiration::Never, 1))
+        );
+
+        sleep(Duration::from_millis(60));
+        assert!(client.try_insert("cooldown", 3, Expiration::Never).unwrap());
+        assert_eq!(
+            client.get::<_, i32>("cooldown"),
+            Some((Expiration::Never, 3))
+        );
+
+        // refresh 重新计时不算写入
+        sleep(Duration::from_millis(60));
+        client.refresh("cooldown").unwrap();
+        assert!(client.try_insert("cooldown", 4, Expiration::Never).unwrap());
+
+        // 其他写入接口同样受限
+        client.insert_bytes("raw", vec![1], Expiration::Never);
+        client.insert_bytes("raw", vec![2], Expiration::Never);
+        assert_eq!(client.get_bytes("raw"), Some((Expiration::Never, vec![1])));
+        client
+            .insert_indexed("indexed", 1u32, Expiration::Never, |_| vec!["i1".into()])
+            .unwrap();
+        client
+            .insert_indexed("indexed", 2u32, Expiration::Never, |_| vec!["i2".into()])
+            .unwrap();
+        assert_eq!(
+            client.get_by_index::<u32>("i1"),
+            Some((Expiration::Never, 1))
+        );
+        assert_eq!(client.get_by_index::<u32>("i2"), None);
+        client.remove("raw");
+        assert!(!client
+            .insert_if_absent("raw", 3u32, Expiration::Never)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_cache_min_write_interval_concurrent() {
+        let config = CacheConfig {
+            min_write_interval: Some(Duration::from_secs(10)),
+            ..CacheConfig::new(1024)
+        };
+        let client = CacheClient::new(config);
+        let barrier = std::sync::Barrier::new(8);
+        let written = AtomicU32::new(0);
+        std::thread::scope(|s| {
+            for i in 0..8u32 {
+                let (client, barrier, written) = (&client, &barrier, &written);
+                s.spawn(move || {
+                    barrier.wait();
+                    if client.try_insert("hot", i, Expiration::Never).unwrap() {
+                        written.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(written.load(Ordering::SeqCst), 1);
+        assert_eq!(client.stats().inserts(), 1);
+    }
+
+    #[test]
+    fn test_cache_get_deserialized_or_raw() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("raw", 300u32, Expiration::Never).unwrap();
+
+        let v = client.get_deserialized_or_raw::<_, u32>("raw");
+        assert!(matches!(v, Some(Either::Left(300))));
+
+        let v = client.get_deserialized_or_raw::<_, String>("raw");
+        let Some(Either::Right((_, raw))) = v else {
+            panic!("expect raw bytes");
+        };
+        let (old, _) =
+            bincode::serde::decode_from_slice::<u32, _>(&raw, config::standard()).unwrap();
+        assert_eq!(old, 300);
+
+        assert!(client
+            .get_deserialized_or_raw::<_, u32>("raw_none")
+            .is_none());
+    }
+
+    #[test]
+    fn test_cache_notify_on_replace() {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        fn on_evict(_: Arc<String>, _: CacheData, _: RemovalCause) {
+            COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+        let config = CacheConfig {
+            callback: Some(on_evict),
+            notify_on_replace: false,
+            ..CacheConfig::new(1024)
+        };
+        let client = CacheClient::new(config);
+        client.insert("replace", 1, Expiration::Never).unwrap();
+        client.insert("replace", 2, Expiration::Never).unwrap();
+        client.check_exp_interval();
+        assert_eq!(COUNT.load(Ordering::SeqCst), 0);
+
+        client.remove("replace");
+        client.check_exp_interval();
+        assert_eq!(COUNT.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_expiration_from_duration() {
+        assert_eq!(Expiration::from(Duration::ZERO), Expiration::Never);
+        assert_eq!(
+            Expiration::from(Duration::from_millis(250)),
+            Expiration::Millis(250)
+        );
+        assert_eq!(
+            Expiration::from(Duration::from_millis(1500)),
+            Expiration::Millis(1500)
+        );
+        assert_eq!(
+            Expiration::from(Duration::from_secs(30)),
+            Expiration::Second(30)
+        );
+
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert_duration("duration", 1, Duration::from_millis(1200))
+            .unwrap();
+        assert_eq!(client.get_exp("duration"), Some(Expiration::Millis(1200)));
+    }
+
+    #[test]
+    fn test_cache_secondary_index() {
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct User {
+            id: u32,
+            email: String,
+        }
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let user = User {
+            id: 1,
+            email: "a@test.com".to_string(),
+        };
+        client
+            .insert_indexed("user:1", user.clone(), Expiration::Never, |u| {
+                vec![u.email.clone()]
+            })
+            .unwrap();
+        assert_eq!(
+            client.get_by_index::<User>("a@test.com"),
+            Some((Expiration::Never, user))
+        );
+
+        // 覆盖写入后旧索引失效
+        let user = User {
+            id: 1,
+            email: "b@test.com".to_string(),
+        };
+        client
+            .insert_indexed("user:1", user.clone(), Expiration::Never, |u| {
+                vec![u.email.clone()]
+            })
+            .unwrap();
+        client.check_exp_interval();
+        assert_eq!(client.get_by_index::<User>("a@test.com"), None);
+        assert_eq!(
+            client.get_by_index::<User>("b@test.com"),
+            Some((Expiration::Never, user))
+        );
+
+        client.remove("user:1");
+        client.check_exp_interval();
+        assert_eq!(client.get_by_index::<User>("b@test.com"), None);
+        let index = client.inner.state.index.lock().unwrap();
+        assert!(index.keys.is_empty());
+        assert!(index.owners.is_empty());
+    }
+
+    #[test]
+    fn test_cache_migrate() {
+        #[derive(Debug, Serialize, Deserialize)]
+        struct V1 {
+            name: String,
+        }
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct V2 {
+            name: String,
+            age: u32,
+            tags: Vec<String>,
+        }
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let v1 = V1 {
+            name: "n".to_string(),
+        };
+        client
+            .insert("migrate", v1, Expiration::Second(60))
+            .unwrap();
+
+        let v = client
+            .migrate::<_, V1, V2, _>("migrate", |old| {
+                Ok(V2 {
+                    name: old.name,
+                    age: 18,
+                    tags: vec![],
+                })
+            })
+            .unwrap();
+        let expect = V2 {
+            name: "n".to_string(),
+            age: 18,
+            tags: vec![],
+        };
+        assert_eq!(v.as_ref(), Some(&expect));
+        assert_eq!(
+            client.get::<_, V2>("migrate"),
+            Some((Expiration::Second(60), expect))
+        );
+
+        assert!(client
+            .migrate::<_, V1, V2, _>("migrate_none", |_| unreachable!())
+            .unwrap()
+            .is_none());
+
+        client
+            .insert("migrate_bad", true, Expiration::Never)
+            .unwrap();
+        assert!(client
+            .migrate::<_, V1, V2, _>("migrate_bad", |_| unreachable!())
+            .is_err());
+        assert!(!client.contains_key("migrate_bad"));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_cache_bytes_shared() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let data = Bytes::from_static(b"shared payload");
+        client.insert_bytes_shared("shared", data.clone(), Expiration::Never);
+
+        let (exp, b) = client.get_bytes_shared("shared").unwrap();
+        assert_eq!(exp, Expiration::Never);
+        assert_eq!(b, data);
+        assert_eq!(b.as_ptr(), data.as_ptr());
+        assert_eq!(client.get_bytes_len("shared"), Some(data.len()));
+        client.check_exp_interval();
+        assert_eq!(client.weighted_size(), data.len() as u64);
+    }
+
+    #[cfg(feature = "tower")]
+    #[test]
+    fn test_cache_layer() {
+        use std::future::Future;
+        use std::task::{Context, Poll, Waker};
+        use tower::{Layer, Service};
+
+        #[derive(Clone)]
+        struct Counter(Arc<AtomicU32>);
+        impl Service<String> for Counter {
+            type Response = String;
+            type Error = anyhow::Error;
+            type Future = std::future::Ready<Result<String>>;
+            fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<()>> {
+                Poll::Ready(Ok(()))
+            }
+            fn call(&mut self, req: String) -> Self::Future {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                std::future::ready(Ok(format!("resp:{req}")))
+            }
+        }
+        fn block_on<F: Future>(f: F) -> F::Output {
+            let mut f = std::pin::pin!(f);
+            let mut cx = Context::from_waker(Waker::noop());
+            loop {
+                if let Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+                    return v;
+                }
+            }
+        }
+
+        init();
+        let calls = Arc::new(AtomicU32::new(0));
+        let layer = CacheLayer::new(KeyExtractorFn(|req: &String| Some(format!("layer:{req}"))))
+            .with_expiration(Expiration::Second(60));
+        let mut svc = layer.layer(Counter(calls.clone()));
+        assert_eq!(block_on(svc.call("a".to_string())).unwrap(), "resp:a");
+        assert_eq!(block_on(svc.call("a".to_string())).unwrap(), "resp:a");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(get_exp("layer:a"), Some(Expiration::Second(60)));
+    }
+
+    #[test]
+    fn test_cache_get_or_insert_with_exp() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let v = client
+            .get_or_insert_with_exp("token", || ("t1".to_string(), Expiration::Second(30)))
+            .unwrap();
+        assert_eq!(v, (Expiration::Second(30), "t1".to_string()));
+
+        let v = client
+            .get_or_insert_with_exp::<_, String, _>("token", || unreachable!())
+            .unwrap();
+        assert_eq!(v, (Expiration::Second(30), "t1".to_string()));
+
+        // 缓存的值无法按 V 解析时重新计算
+        client.insert_bytes("token", vec![10, b'a'], Expiration::Never);
+        let v = client
+            .get_or_insert_with_exp("token", || ("t2".to_string(), Expiration::Second(30)))
+            .unwrap();
+        assert_eq!(v, (Expiration::Second(30), "t2".to_string()));
+        assert_eq!(
+            client.get::<_, String>("token"),
+            Some((Expiration::Second(30), "t2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_cache_get_or_insert_with_exp_backoff() {
+        let client = CacheBuilder::new(1024)
+            .loader_failure_ttl(Duration::from_millis(200))
+            .build();
+        assert!(client
+            .get_or_insert_try_with::<_, u32, _>("k", Expiration::Never, || Err(anyhow!("down")))
+            .is_err());
+        // 与 get_or_insert_try_with 共享失败记录, ttl 内不调用 f
+        let e = client
+            .get_or_insert_with_exp::<_, u32, _>("k", || unreachable!())
+            .unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<CacheError>(),
+            Some(CacheError::LoaderBackoff { error, .. }) if error == "down"
+        ));
+
+        std::thread::sleep(Duration::from_millis(250));
+        let v = client
+            .get_or_insert_with_exp("k", || (1u32, Expiration::Never))
+            .unwrap();
+        assert_eq!(v, (Expiration::Never, 1));
+    }
+
+    #[test]
+    fn test_cache_get_or_insert_with_exp_frozen() {
+        let client = Arc::new(CacheClient::new(CacheConfig::new(1024)));
+        client.insert("k", 1u32, Expiration::Never).unwrap();
+        client.freeze();
+        let reader = {
+            let client = client.clone();
+            std::thread::spawn(move || {
+                client
+                    .get_or_insert_with_exp::<_, u32, _>("k", || unreachable!())
+                    .unwrap()
+            })
+        };
+        sleep(Duration::from_millis(100));
+        client.insert("k", 2u32, Expiration::Never).unwrap();
+        client.thaw();
+        // 冻结期间的读取等待 thaw, 读到的是新值
+        assert_eq!(reader.join().unwrap(), (Expiration::Never, 2));
+    }
+
+    #[test]
+    fn test_cache_expire_after_read_reset() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("sliding", 1, Expiration::Millis(100))
+            .unwrap();
+        client
+            .expire_after_read_reset("sliding", Expiration::Second(60))
+            .unwrap();
+        sleep(Duration::from_millis(150));
+        assert_eq!(
+            client.get::<_, i32>("sliding"),
+            Some((Expiration::Second(60), 1))
+        );
+        assert!(client
+            .expire_after_read_reset("sliding_none", Expiration::Never)
+            .is_err());
+    }
+
+    #[test]
+    fn test_cache_remove_typed() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("tmp_dir", "/tmp/a".to_string(), Expiration::Never)
+            .unwrap();
+        assert_eq!(
+            client.remove_typed::<_, String>("tmp_dir"),
+            Some((Expiration::Never, "/tmp/a".to_string()))
+        );
+        assert!(!client.contains_key("tmp_dir"));
+        assert_eq!(client.remove_typed::<_, String>("tmp_dir"), None);
+
+        client.insert("tmp_bad", true, Expiration::Never).unwrap();
+        assert!(client.try_remove_typed::<_, String>("tmp_bad").is_err());
+        assert!(!client.contains_key("tmp_bad"));
+    }
+
+    #[test]
+    fn test_cache_soft_threshold() {
+        let fired = Arc::new(AtomicU32::new(0));
+        let f = fired.clone();
+        let config = CacheConfig {
+            opportunistic_maintenance: 1,
+            soft_capacity_threshold: Some(0.5),
+            on_soft_threshold: Some(Arc::new(move |_, _| {
+                f.fetch_add(1, Ordering::SeqCst);
+            })),
+            ..CacheConfig::new(100)
+        };
+        let client = CacheClient::new(config);
+        client
+            .insert("soft1", vec![0u8; 20], Expiration::Never)
+            .unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+        client
+            .insert("soft2", vec![0u8; 40], Expiration::Never)
+            .unwrap();
+        client
+            .insert("soft3", vec![0u8; 1], Expiration::Never)
+            .unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 1);
+
+        client.remove("soft2");
+        client.get::<_, Vec<u8>>("soft1");
+        client
+            .insert("soft2", vec![0u8; 40], Expiration::Never)
+            .unwrap();
+        assert_eq!(fired.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cache_refresh_before_expiry() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("expiring", 1, Expiration::Millis(300))
+            .unwrap();
+        client.insert("fresh", 2, Expiration::Second(60)).unwrap();
+        client.insert("forever", 3, Expiration::Never).unwrap();
+        assert_eq!(client.refresh_before_expiry(0.5), 0);
+
+        sleep(Duration::from_millis(200));
+        assert_eq!(client.refresh_before_expiry(0.5), 1);
+        sleep(Duration::from_millis(200));
+        assert_eq!(
+            client.get::<_, i32>("expiring"),
+            Some((Expiration::Millis(300), 1))
+        );
+    }
+
+    #[test]
+    fn test_cache_capacity_headroom() {
+        let client = CacheClient::new(CacheConfig::new(100));
+        assert_eq!(client.capacity(), 100);
+        assert_eq!(client.get_max_capacity(), Some(100));
+        client
+            .insert("room", vec![0u8; 29], Expiration::Never)
+            .unwrap();
+        client.check_exp_interval();
+        assert_eq!(client.headroom(), 70);
+    }
+
+    #[test]
+    fn test_cache_set_max_capacity() {
+        let client = CacheClient::new(CacheConfig::new(4));
+        client.insert("a", 1, Expiration::Millis(300)).unwrap();
+        for i in 0..3 {
+            client
+                .insert(format!("k{}", i), i, Expiration::Never)
+                .unwrap();
+        }
+        assert!(client.set_max_capacity(0).is_err());
+
+        client.set_max_capacity(10).unwrap();
+        assert_eq!(client.get_max_capacity(), Some(10));
+        assert_eq!(client.capacity(), 10);
+        assert_eq!(client.entry_count(), 4);
+        assert_eq!(client.get::<_, i32>("k1"), Some((Expiration::Never, 1)));
+        for i in 3..8 {
+            client
+                .insert(format!("k{}", i), i, Expiration::Never)
+                .unwrap();
+        }
+        client.check_exp_interval();
+        assert_eq!(client.entry_count(), 9);
+
+        // 迁移的 entry 保留原来的过期时间
+        sleep(Duration::from_millis(350));
+        client.check_exp_interval();
+        assert!(!client.contains_key("a"));
+
+        client.set_max_capacity(2).unwrap();
+        client.check_exp_interval();
+        assert_eq!(client.get_max_capacity(), Some(2));
+        assert!(client.entry_count() <= 2);
+    }
+
+    #[test]
+    fn test_cache_get_or_insert_with_retry() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let mut calls = 0;
+        let v = client
+            .get_or_insert_with_retry(
+                "retry",
+                Expiration::Never,
+                || {
+                    calls += 1;
+                    if calls < 3 {
+                        return Err(anyhow!("backend down"));
+                    }
+                    Ok(calls)
+                },
+                3,
+                Duration::from_millis(1),
+            )
+            .unwrap();
+        assert_eq!(v, 3);
+        assert_eq!(client.get::<_, i32>("retry"), Some((Expiration::Never, 3)));
+
+        let mut calls = 0;
+        let r = client.get_or_insert_with_retry::<_, i32, _>(
+            "retry_fail",
+            Expiration::Never,
+            || {
+                calls += 1;
+                Err(anyhow!("error {calls}"))
+            },
+            2,
+            Duration::from_millis(1),
+        );
+        assert_eq!(r.unwrap_err().to_string(), "error 3");
+        assert!(!client.contains_key("retry_fail"));
+    }
+
+    #[test]
+    fn test_cache_length_prefix() {
+        let client = CacheClient::new(CacheConfig {
+            length_prefix: true,
+            ..CacheConfig::new(1024)
+        });
+        client.insert("framed", 300u32, Expiration::Never).unwrap();
+        assert_eq!(
+            client.get::<_, u32>("framed"),
+            Some((Expiration::Never, 300))
+        );
+        let (_, framed) = client.get_framed("framed").unwrap();
+        assert_eq!(framed, vec![0, 0, 0, 3, 251, 44, 1]);
+        assert_eq!(
+            client.get_bytes("framed"),
+            Some((Expiration::Never, vec![251, 44, 1]))
+        );
+
+        client.insert_bytes("framed_raw", b"abc".to_vec(), Expiration::Never);
+        assert_eq!(
+            client.get_framed("framed_raw"),
+            Some((Expiration::Never, vec![0, 0, 0, 3, b'a', b'b', b'c']))
+        );
+        assert_eq!(
+            client.get_bytes("framed_raw"),
+            Some((Expiration::Never, b"abc".to_vec()))
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_cache_bounded_channel() {
+        let (client, mut rx) = CacheClient::with_bounded_channel(
+            CacheConfig::new(1024),
+            1,
+            ChannelFullPolicy::DropNewest,
+        );
+        for k in ["ch1", "ch2", "ch3"] {
+            client.insert(k, 1, Expiration::Never).unwrap();
+            client.remove(k);
+        }
+        client.check_exp_interval();
+        assert_eq!(client.pending_events(), 1);
+        let (k, _, cause) = rx.try_recv().unwrap();
+        assert_eq!(client.pending_events(), 0);
+        assert_eq!(k, "ch1");
+        assert_eq!(cause, RemovalCause::Explicit);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(client.dropped_events(), 2);
+    }
+
+    #[test]
+    fn test_cache_extend() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .extend([("seed1", 1), ("seed2", 2)], Expiration::Never)
+            .unwrap();
+        assert_eq!(client.get::<_, i32>("seed2"), Some((Expiration::Never, 2)));
+        // 先完成全局初始化, 避免抢先初始化导致其他测试的 init() 失败
+        init();
+        assert!(setup_with_initial_entries([("seed", 1)], Expiration::Never, 16).is_err());
+    }
+
+    #[test]
+    fn test_cache_deterministic_encoding() {
+        use std::collections::{BTreeMap, HashMap};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Doc {
+            id: u32,
+            tags: HashMap<String, Vec<u8>>,
+            nested: Option<HashMap<u16, HashMap<String, u8>>>,
+        }
+        let doc = |rev: bool| {
+            let mut ids: Vec<u16> = (0..32).collect();
+            if rev {
+                ids.reverse();
+            }
+            let mut tags = HashMap::new();
+            let mut nested = HashMap::new();
+            for i in ids {
+                tags.insert(format!("t{i}"), vec![i as u8]);
+                nested.insert(i, HashMap::from([(format!("n{i}"), i as u8)]));
+            }
+            Doc {
+                id: 7,
+                tags,
+                nested: Some(nested),
+            }
+        };
+
+        let client = CacheClient::new(CacheConfig {
+            deterministic_encoding: true,
+            ..CacheConfig::new(1024 * 1024)
+        });
+        client.insert("a", doc(false), Expiration::Never).unwrap();
+        client.insert("b", doc(true), Expiration::Never).unwrap();
+        assert_eq!(client.get_bytes("a"), client.get_bytes("b"));
+        assert_eq!(client.get::<_, Doc>("a").unwrap().1, doc(true));
+
+        // 不含 HashMap 的类型与普通编码逐字节相同
+        let plain = CacheClient::new(CacheConfig::new(1024));
+        let v = (
+            "s".to_string(),
+            vec![1u64, 300],
+            BTreeMap::from([(2u8, -1i32), (1, 5)]),
+            Some('x'),
+        );
+        client.insert("c", &v, Expiration::Never).unwrap();
+        plain.insert("c", &v, Expiration::Never).unwrap();
+        assert_eq!(client.get_bytes("c"), plain.get_bytes("c"));
+    }
+
+    #[test]
+    fn test_cache_downgrade_expiration() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("short", 1u8, Expiration::Second(1)).unwrap();
+        client.insert("long", 1u8, Expiration::Hour(1)).unwrap();
+        client.insert("never", 1u8, Expiration::Never).unwrap();
+
+        assert!(!client
+            .downgrade_expiration("short", Expiration::Minute(5))
+            .unwrap());
+        assert!(!client
+            .downgrade_expiration("short", Expiration::Never)
+            .unwrap());
+        assert_eq!(client.get_exp("short"), Some(Expiration::Second(1)));
+
+        assert!(client
+            .downgrade_expiration("long", Expiration::Minute(5))
+            .unwrap());
+        assert_eq!(client.get_exp("long"), Some(Expiration::Minute(5)));
+        assert!(client
+            .downgrade_expiration("never", Expiration::Second(30))
+            .unwrap());
+        assert_eq!(client.get_exp("never"), Some(Expiration::Second(30)));
+
+        assert!(client
+            .downgrade_expiration("missing", Expiration::Second(1))
+            .is_err());
+        sleep(Duration::from_millis(1100));
+        assert!(!client.contains_key("short"));
+    }
+
+    #[test]
+    fn test_cache_upgrade_expiration() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("session", 1u8, Expiration::Second(1))
+            .unwrap();
+        client.insert("never", 1u8, Expiration::Never).unwrap();
+
+        assert!(!client
+            .upgrade_expiration("session", Expiration::Millis(500))
+            .unwrap());
+        assert!(client
+            .upgrade_expiration("session", Expiration::Second(3))
+            .unwrap());
+        assert_eq!(client.get_exp("session"), Some(Expiration::Second(3)));
+        assert!(!client
+            .upgrade_expiration("never", Expiration::Hour(1))
+            .unwrap());
+        assert!(client
+            .upgrade_expiration("missing", Expiration::Never)
+            .is_err());
+
+        sleep(Duration::from_millis(1100));
+        assert!(client.contains_key("session"));
+        assert!(client
+            .upgrade_expiration("session", Expiration::Never)
+            .unwrap());
+        assert_eq!(client.get_exp("session"), Some(Expiration::Never));
+    }
+
+    #[test]
+    fn test_cache_bulk_load() {
+        let client = Arc::new(CacheClient::new(CacheConfig::new(1024 * 1024)));
+        client.insert("k0", 100u32, Expiration::Never).unwrap();
+        let encode = |v: u32| bincode::serde::encode_to_vec(v, config::standard()).unwrap();
+        let entries: Vec<(String, CacheData)> = (0..1000)
+            .map(|i| (format!("k{i}"), (Expiration::Never, encode(i))))
+            .collect();
+
+        assert_eq!(client.bulk_load(entries.clone(), false), 999);
+        assert_eq!(client.get::<_, u32>("k0").unwrap().1, 100);
+        assert_eq!(client.get::<_, u32>("k999").unwrap().1, 999);
+        assert_eq!(client.bulk_load(entries[..1].to_vec(), true), 1);
+        assert_eq!(client.get::<_, u32>("k0").unwrap().1, 0);
+
+        // 读操作要么看不到, 要么看到全部
+        let entries: Vec<(String, CacheData)> = (0..2000)
+            .map(|i| (format!("n{i}"), (Expiration::Never, encode(i))))
+            .collect();
+        let c = client.clone();
+        let reader = std::thread::spawn(move || loop {
+            // 先装载 n0 后装载 n1999: 看到 n0 时 n1999 必须已经可见
+            let first = c.contains_key("n0");
+            let last = c.contains_key("n1999");
+            if first {
+                assert!(last);
+                break;
+            }
+        });
+        client.bulk_load(entries, true);
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_cache_get_or_parse() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let calls = AtomicU32::new(0);
+        let parse = |s: &str| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            s.parse::<u32>()
+        };
+
+        assert_eq!(
+            client.get_or_parse("42", Expiration::Never, parse),
+            Ok((Expiration::Never, 42))
+        );
+        assert_eq!(
+            client.get_or_parse("42", Expiration::Never, parse),
+            Ok((Expiration::Never, 42))
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // 失败不缓存
+        assert!(client.get_or_parse("x", Expiration::Never, parse).is_err());
+        assert!(client.get_or_parse("x", Expiration::Never, parse).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // 同一 source 解析成不同类型互不影响
+        let (_, v) = client
+            .get_or_parse("42", Expiration::Never, |s| s.parse::<u64>())
+            .unwrap();
+        assert_eq!(v, 42u64);
+        assert!(!client.contains_key("42"));
+
+        // key 只包含 source 的哈希, 长度固定
+        let long = "9".repeat(4096);
+        assert!(client
+            .get_or_parse(&long, Expiration::Never, |s| s.parse::<f64>())
+            .is_ok());
+        assert!(client.export().keys().all(|k| k.len() < 64));
+
+        // 无法读取的缓存数据被重新解析的结果覆盖; f 中可以再调用 get_or_parse
+        let key = client
+            .export()
+            .into_keys()
+            .find(|k| k.starts_with("get_or_parse:u32:"))
+            .unwrap();
+        client.insert_bytes(key, Vec::new(), Expiration::Never);
+        let nested = |s: &str| {
+            client
+                .get_or_parse(s, Expiration::Never, |s| s.parse::<u64>())
+                .map(|(_, v)| v as u32)
+        };
+        assert_eq!(
+            client.get_or_parse("42", Expiration::Never, nested),
+            Ok((Expiration::Never, 42))
+        );
+        assert_eq!(
+            client.get_or_parse::<u32, std::num::ParseIntError, _>(
+                "42",
+                Expiration::Never,
+                |_| unreachable!()
+            ),
+            Ok((Expiration::Never, 42))
+        );
+
+        // key 超过 max_key_len 时不缓存
+        let client = CacheBuilder::new(1024).max_key_len(16).build();
+        assert!(client.get_or_parse("1", Expiration::Never, parse).is_ok());
+        assert!(client.get_or_parse("1", Expiration::Never, parse).is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+        assert_eq!(client.entry_count(), 0);
+    }
+
+    #[test]
+    fn test_cache_get_or_parse_concurrent() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let calls = AtomicU32::new(0);
+        let barrier = std::sync::Barrier::new(8);
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        barrier.wait();
+                        client.get_or_parse("42", Expiration::Never, |s| {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            sleep(Duration::from_millis(50));
+                            s.parse::<u32>()
+                        })
+                    })
+                })
+                .collect();
+            for h in handles {
+                assert_eq!(h.join().unwrap(), Ok((Expiration::Never, 42)));
+            }
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // 失败不缓存, 等待者各自解析并返回自己的错误
+        let failed = AtomicU32::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let r = client.get_or_parse("x", Expiration::Never, |s| {
+                        failed.fetch_add(1, Ordering::SeqCst);
+                        sleep(Duration::from_millis(20));
+                        s.parse::<u32>()
+                    });
+                    assert!(r.is_err());
+                });
+            }
+        });
+        assert_eq!(failed.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_cache_check_exp_interval_every() {
+        let handle = check_exp_interval_every(1);
+        sleep(Duration::from_millis(1200));
+        assert!(!handle.is_finished());
+        stop_exp_interval_task();
+        handle.join().unwrap();
+
+        let handle = check_exp_interval_every(60);
+        let start = Instant::now();
+        shutdown();
+        handle.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        shutdown();
+    }
+
+    #[test]
+    fn test_cache_for_each() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.insert("b", 2u32, Expiration::Never).unwrap();
+        client.insert("c", "text", Expiration::Second(5)).unwrap();
+
+        let keys = Mutex::new(Vec::new());
+        let n = client
+            .for_each(|k, (exp, _)| keys.lock().unwrap().push((k.to_string(), *exp)))
+            .unwrap();
+        assert_eq!(n, 3);
+        let mut keys = keys.into_inner().unwrap();
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(keys[2], ("c".to_string(), Expiration::Second(5)));
+
+        let texts = Mutex::new(Vec::new());
+        let (visited, decoded) = client
+            .for_each_typed(|k, v: String| texts.lock().unwrap().push((k.to_string(), v)))
+            .unwrap();
+        assert_eq!((visited, decoded), (3, 1));
+        assert_eq!(
+            texts.into_inner().unwrap(),
+            vec![("c".to_string(), "text".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_cache_insert_or_extend_ttl() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        assert_eq!(
+            client
+                .insert_or_extend_ttl("k", 1u32, Expiration::Second(1))
+                .unwrap(),
+            InsertResult::Inserted
+        );
+        assert_eq!(
+            client
+                .insert_or_extend_ttl("k", 1u32, Expiration::Minute(1))
+                .unwrap(),
+            InsertResult::Extended
+        );
+        assert_eq!(client.get_exp("k"), Some(Expiration::Minute(1)));
+        assert_eq!(
+            client
+                .insert_or_extend_ttl("k", 2u32, Expiration::Never)
+                .unwrap(),
+            InsertResult::Updated {
+                old_value: vec![1],
+                old_exp: Expiration::Minute(1)
+            }
+        );
+        assert_eq!(client.get::<_, u32>("k"), Some((Expiration::Never, 2)));
+    }
+
+    #[test]
+    fn test_cache_insert_with_on_evict() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let causes = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let causes = causes.clone();
+            move |cause| causes.lock().unwrap().push((name, cause))
+        };
+
+        client
+            .insert_with_on_evict("a", 1u8, Expiration::Never, record("a1"))
+            .unwrap();
+        client
+            .insert_with_on_evict("a", 2u8, Expiration::Never, record("a2"))
+            .unwrap();
+        client
+            .insert_with_on_evict("b", 1u8, Expiration::Millis(100), record("b"))
+            .unwrap();
+        client.insert("c", 1u8, Expiration::Never).unwrap();
+        client.remove("a");
+        client.remove("c");
+        // moka 的 timer wheel 最小粒度约 1 秒, 过期通知可能晚于 ttl
+        sleep(Duration::from_millis(1500));
+        client.check_exp_interval();
+
+        let mut causes = causes.lock().unwrap().clone();
+        causes.sort_by_key(|(name, _)| *name);
+        assert_eq!(
+            causes,
+            vec![
+                ("a1", RemovalCause::Replaced),
+                ("a2", RemovalCause::Explicit),
+                ("b", RemovalCause::Expired),
+            ]
+        );
+        assert!(client.inner.state.on_evict.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_stats_reset() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u8, Expiration::Never).unwrap();
+        assert!(client.get::<_, u8>("a").is_some());
+        assert!(client.get::<_, u8>("a").is_some());
+        assert!(client.get::<_, u8>("b").is_none());
+        client
+            .get_or_insert_with_exp("c", || (3u8, Expiration::Never))
+            .unwrap();
+
+        let stats = client.stats();
+        assert_eq!((stats.hits(), stats.misses(), stats.inserts()), (2, 2, 2));
+        let snap = stats.snapshot_and_reset();
+        assert_eq!((snap.hits(), snap.misses(), snap.inserts()), (2, 2, 2));
+        assert_eq!((stats.hits(), stats.misses(), stats.inserts()), (0, 0, 0));
+
+        assert!(client.get::<_, u8>("c").is_some());
+        assert_eq!(stats.hits(), 1);
+        stats.reset();
+        assert_eq!(stats.hits(), 0);
+    }
+
+    #[test]
+    fn test_cache_expiration_checked_add() {
+        let min = Duration::from_secs(60);
+        assert_eq!(
+            Expiration::Minute(1).checked_add(min),
+            Some(Expiration::Minute(2))
+        );
+        assert_eq!(
+            Expiration::Minute(1).checked_add(Duration::from_secs(30)),
+            Some(Expiration::Second(90))
+        );
+        assert_eq!(
+            Expiration::Second(1).checked_add(Duration::from_millis(500)),
+            Some(Expiration::Millis(1500))
+        );
+        assert_eq!(
+            Expiration::Millis(1).checked_add(Duration::from_secs(1)),
+            Some(Expiration::Millis(1001))
+        );
+        assert_eq!(Expiration::Never.checked_add(min), Some(Expiration::Never));
+
+        // u64 边界
+        assert_eq!(
+            Expiration::Hour(u64::MAX - 1).checked_add(Duration::from_secs(3600)),
+            Some(Expiration::Hour(u64::MAX))
+        );
+        assert_eq!(
+            Expiration::Hour(u64::MAX).checked_add(Duration::from_secs(3600)),
+            None
+        );
+        assert_eq!(
+            Expiration::Hour(u64::MAX).checked_add(Duration::from_secs(1)),
+            None
+        );
+        assert_eq!(
+            Expiration::Millis(u64::MAX).checked_add(Duration::from_millis(1)),
+            None
+        );
+        assert_eq!(
+            Expiration::Second(u64::MAX).checked_add(Duration::ZERO),
+            Some(Expiration::Second(u64::MAX))
+        );
+        assert_eq!(
+            Expiration::Minute(u64::MAX).saturating_add(min),
+            Expiration::Minute(u64::MAX)
+        );
+        assert_eq!(
+            Expiration::Millis(u64::MAX - 1).saturating_add(Duration::from_secs(1)),
+            Expiration::Millis(u64::MAX)
+        );
+        assert_eq!(
+            Expiration::Hour(u64::MAX).as_duration(),
+            Some(Duration::from_secs(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn test_cache_strict_expiry() {
+        let client = CacheClient::new(CacheConfig {
+            strict_expiry: true,
+            ..CacheConfig::new(1024)
+        });
+        client.insert("token", 1u8, Expiration::Millis(1)).unwrap();
+        sleep(Duration::from_millis(2));
+        assert_eq!(client.get::<_, u8>("token"), None);
+        assert_eq!(client.get_bytes("token"), None);
+        assert!(!client.contains_key("token"));
+
+        client.insert("live", 1u8, Expiration::Second(10)).unwrap();
+        client.insert("never", 1u8, Expiration::Never).unwrap();
+        assert!(client.get::<_, u8>("live").is_some());
+        assert!(client.contains_key("never"));
+    }
+
+    #[test]
+    fn test_cache_codec() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct User {
+            name: String,
+            age: u8,
+        }
+        let user = || User {
+            name: "a".to_string(),
+            age: 3,
+        };
+
+        let client = CacheBuilder::new(1024).codec(Codec::Bincode).build();
+        client.insert("u", user(), Expiration::Never).unwrap();
+        assert_eq!(client.get_bytes("u").unwrap().1, vec![1, b'a', 3]);
+
+        #[cfg(feature = "json")]
+        {
+            let client = CacheBuilder::new(1024).codec(Codec::Json).build();
+            client.insert("u", user(), Expiration::Never).unwrap();
+            assert_eq!(client.get::<_, User>("u").unwrap().1, user());
+            assert_eq!(
+                client.get_bytes("u").unwrap().1,
+                br#"{"name":"a","age":3}"#.to_vec()
+            );
+            assert!(client.try_get::<_, u32>("u").is_err());
+        }
+
+        #[cfg(feature = "msgpack")]
+        {
+            let client = CacheBuilder::new(1024).codec(Codec::Msgpack).build();
+            client.insert("u", user(), Expiration::Never).unwrap();
+            assert_eq!(client.get::<_, User>("u").unwrap().1, user());
+        }
+    }
+
+    #[test]
+    fn test_cache_type_tag() {
+        let client = CacheClient::new(CacheConfig {
+            length_prefix: true,
+            ..CacheConfig::new(1024)
+        });
+        client.insert_bytes_with_type_tag("k", "app::User", b"raw".to_vec(), Expiration::Second(9));
+        assert_eq!(
+            client.get_bytes_with_type_tag("k"),
+            Some((
+                "app::User".to_string(),
+                b"raw".to_vec(),
+                Expiration::Second(9)
+            ))
+        );
+        assert_eq!(
+            client.get_bytes("k"),
+            Some((Expiration::Second(9), b"raw".to_vec()))
+        );
+
+        client.insert_bytes("k", b"raw".to_vec(), Expiration::Never);
+        assert_eq!(client.get_bytes_with_type_tag("k"), None);
+        assert_eq!(client.get_bytes_with_type_tag("missing"), None);
+    }
+
+    #[test]
+    fn test_cache_remove_many() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u8, Expiration::Never).unwrap();
+        client.insert("b", 1u8, Expiration::Second(5)).unwrap();
+        client.insert("c", 1u8, Expiration::Never).unwrap();
+
+        assert_eq!(client.remove_many(&["a", "x"]), 1);
+        assert!(!client.contains_key("a"));
+        let keys = vec!["b".to_string(), "c".to_string(), "a".to_string()];
+        let mut removed = client.remove_many_detailed(&keys);
+        removed.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            removed,
+            vec![
+                ("b".to_string(), Expiration::Second(5)),
+                ("c".to_string(), Expiration::Never)
+            ]
+        );
+        assert_eq!(client.remove_many::<&str>(&[]), 0);
+    }
+
+    #[test]
+    fn test_cache_try_setup() {
+        init();
+        assert!(!try_setup(None, 16).unwrap());
+        assert!(setup(None, 16).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct TypedSession {
+        user: String,
+    }
+
+    typed_cache! {
+        TYPED_COUNTER: u64,
+        TYPED_SESSION: TypedSession,
+    }
+
+    #[test]
+    fn test_cache_typed_macro() {
+        init();
+        TYPED_COUNTER::insert("a", 7, Expiration::Never).unwrap();
+        TYPED_SESSION::insert(
+            "a",
+            TypedSession {
+                user: "u".to_string(),
+            },
+            Expiration::Never,
+        )
+        .unwrap();
+        assert_eq!(TYPED_COUNTER::get("a"), Some((Expiration::Never, 7)));
+        assert_eq!(TYPED_SESSION::get("a").unwrap().1.user, "u");
+        assert_eq!(TYPED_SESSION::key("a"), "TYPED_SESSION:a");
+        assert!(!contains_key("a"));
+
+        TYPED_COUNTER::remove("a");
+        assert!(!TYPED_COUNTER::contains_key("a"));
+        assert!(TYPED_SESSION::contains_key("a"));
+    }
+
+    #[test]
+    fn test_cache_json_entry() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let json = r#"{"id":1,"name":"a"}"#;
+        client.insert_json("j", json, Expiration::Never).unwrap();
+        assert_eq!(client.get_json("j").as_deref(), Some(json));
+        assert_eq!(client.get_bytes("j").unwrap().1, json.as_bytes());
+        assert_eq!(
+            client.try_get::<_, String>("j"),
+            Err(CacheError::ContentTypeMismatch(ContentType::Json))
+        );
+        assert_eq!(client.get::<_, String>("j"), None);
+
+        client.insert("s", json, Expiration::Never).unwrap();
+        assert_eq!(client.get_json("s"), None);
+        assert_eq!(client.get_json("missing"), None);
+    }
+
+    #[test]
+    fn test_cache_notify_delay() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u8, Expiration::Millis(1)).unwrap();
+        sleep(Duration::from_millis(1200));
+        client.check_exp_interval();
+        let delay = client.stats().max_expire_notify_delay();
+        assert!(delay > Duration::ZERO && delay < Duration::from_secs(10));
+        client.stats().reset();
+        assert_eq!(client.stats().max_expire_notify_delay(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_cache_assert_helpers() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.assert_empty();
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.assert_contains("a");
+        client.assert_entry_eq("a", 1u32);
+
+        let msg = |f: &dyn Fn()| {
+            let e = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err();
+            e.downcast_ref::<String>().cloned().unwrap()
+        };
+        assert!(msg(&|| client.assert_empty()).contains(r#"1 entries, keys: ["a"]"#));
+        assert!(msg(&|| client.assert_contains("b")).contains(r#"key "b" not found"#));
+        assert!(msg(&|| client.assert_entry_eq("a", 2u32)).contains("actual: 1"));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn test_cache_share_bytes() {
+        let shared = CacheClient::new(CacheConfig {
+            share_bytes: true,
+            ..CacheConfig::new(1024 * 1024)
+        });
+        shared.insert_bytes("big", vec![7; 4096], Expiration::Never);
+        let (_, a) = shared.get_bytes_shared("big").unwrap();
+        let (_, b) = shared.get_bytes_shared("big").unwrap();
+        assert_eq!(a.as_ptr(), b.as_ptr());
+        let (_, mut copy) = shared.get_bytes("big").unwrap();
+        assert_ne!(copy.as_ptr(), a.as_ptr());
+        copy[0] = 0;
+        assert_eq!(shared.get_bytes("big").unwrap().1[0], 7);
+        assert_eq!(shared.weighted_size(), 0);
+        shared.check_exp_interval();
+        assert_eq!(shared.weighted_size(), 4096);
+
+        shared.insert("typed", 42u32, Expiration::Never).unwrap();
+        assert_eq!(shared.get::<_, u32>("typed").unwrap().1, 42);
+
+        let copied = CacheClient::new(CacheConfig::new(1024 * 1024));
+        copied.insert_bytes("big", vec![7; 4096], Expiration::Never);
+        let (_, a) = copied.get_bytes_shared("big").unwrap();
+        let (_, b) = copied.get_bytes_shared("big").unwrap();
+        assert_ne!(a.as_ptr(), b.as_ptr());
+    }
+
+    #[test]
+    fn test_cache_multi_set_if_absent() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        assert!(!client
+            .insert_if_absent("a", 9u32, Expiration::Never)
+            .unwrap());
+        let n = client
+            .multi_set_if_absent(
+                [("a", 2u32), ("b", 2), ("c", 3), ("b", 4)],
+                Expiration::Never,
+            )
+            .unwrap();
+        assert_eq!(n, 2);
+        assert_eq!(client.get::<_, u32>("a").unwrap().1, 1);
+        assert_eq!(client.get::<_, u32>("b").unwrap().1, 2);
+        assert_eq!(client.get::<_, u32>("c").unwrap().1, 3);
+    }
+
+    #[test]
+    fn test_cache_try_insert_without_evicting() {
+        let client = CacheClient::new(CacheConfig::new(100));
+        assert!(client
+            .try_insert_without_evicting("a", vec![0u8; 59], Expiration::Never)
+            .unwrap());
+        // vec 编码后多 1 字节长度: 60 + 41 > 100
+        assert!(!client
+            .try_insert_without_evicting("b", vec![0u8; 40], Expiration::Never)
+            .unwrap());
+        assert!(client
+            .try_insert_without_evicting("b", vec![0u8; 39], Expiration::Never)
+            .unwrap());
+        // 替换同一个 key 时旧的权重不计入
+        assert!(client
+            .try_insert_without_evicting("a", vec![0u8; 59], Expiration::Never)
+            .unwrap());
+        client.check_exp_interval();
+        assert_eq!(client.weighted_size(), 100);
+        assert!(client.contains_key("a") && client.contains_key("b"));
+
+        let counted = CacheClient::new(CacheConfig {
+            weigher: None,
+            ..CacheConfig::new(1)
+        });
+        assert!(counted
+            .try_insert_without_evicting("a", 1u8, Expiration::Never)
+            .unwrap());
+        assert!(!counted
+            .try_insert_without_evicting("b", 1u8, Expiration::Never)
+            .unwrap());
+        assert!(counted
+            .try_insert_without_evicting("a", 2u8, Expiration::Never)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_cache_time_to_live() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("never", 1u8, Expiration::Never).unwrap();
+        client.insert("timed", 1u8, Expiration::Second(10)).unwrap();
+        assert_eq!(client.time_to_live("missing"), None);
+        assert_eq!(client.time_to_live("never"), Some(None));
+        let ttl = client.time_to_live("timed").unwrap().unwrap();
+        assert!(ttl <= Duration::from_secs(10) && ttl > Duration::from_secs(9));
+    }
+
+    #[cfg(feature = "trace-ops")]
+    #[test]
+    fn test_cache_trace_ops() {
+        let ops = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ops.clone();
+        let client = CacheClient::new(CacheConfig {
+            on_op: Some(Arc::new(move |t: &OpTrace| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((t.op, t.key.to_string(), t.hit, t.bytes))
+            })),
+            ..CacheConfig::new(1024)
+        });
+        client.insert("a", "xy", Expiration::Never).unwrap();
+        client.get::<_, String>("a");
+        client.get::<_, String>("b");
+        client.remove_typed::<_, String>("a");
+        client.remove("a");
+        assert_eq!(
+            *ops.lock().unwrap(),
+            vec![
+                (OpKind::Insert, "a".to_string(), None, 3),
+                (OpKind::Get, "a".to_string(), Some(true), 3),
+                (OpKind::Get, "b".to_string(), Some(false), 0),
+                (OpKind::Remove, "a".to_string(), Some(true), 3),
+                (OpKind::Remove, "a".to_string(), None, 0),
+            ]
+        );
+    }
+
+    #[cfg(feature = "access-log")]
+    #[test]
+    fn test_cache_access_log() {
+        let client = CacheBuilder::new(1024).access_log_capacity(3).build();
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        for k in ["a", "b", "a", "c"] {
+            client.get::<_, u32>(k);
+        }
+        let keys: Vec<String> = client
+            .recent_accesses(10)
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec!["c", "a", "b"]);
+        let recent = client.recent_accesses(2);
+        assert_eq!(recent.len(), 2);
+        assert!(recent[0].1 >= recent[1].1);
+
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.get::<_, u32>("a");
+        assert!(client.recent_accesses(1).is_empty());
+    }
+
+    #[test]
+    fn test_cache_get_and_extend() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("s", 5u32, Expiration::Second(1)).unwrap();
+        client.insert("n", 6u32, Expiration::Never).unwrap();
+
+        assert_eq!(
+            client
+                .get_and_extend::<_, u32>("s", Duration::from_secs(2))
+                .unwrap(),
+            Some(5)
+        );
+        let ttl = client.time_to_live("s").unwrap().unwrap();
+        assert!(ttl > Duration::from_secs(2) && ttl <= Duration::from_secs(3));
+        assert_eq!(
+            client
+                .get_and_extend::<_, u32>("n", Duration::from_secs(2))
+                .unwrap(),
+            Some(6)
+        );
+        assert_eq!(client.get_exp("n"), Some(Expiration::Never));
+        assert_eq!(
+            client
+                .get_and_extend::<_, u32>("missing", Duration::from_secs(2))
+                .unwrap(),
+            None
+        );
+        sleep(Duration::from_millis(1200));
+        assert!(client.contains_key("s"));
+    }
+
+    #[test]
+    fn test_cache_rekey_all() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("user_1", 1u32, Expiration::Second(30))
+            .unwrap();
+        client.insert("user_2", 2u32, Expiration::Never).unwrap();
+        client.insert("u:2", 20u32, Expiration::Never).unwrap();
+        client.insert("tmp", 3u32, Expiration::Never).unwrap();
+
+        let n = client
+            .rekey_all(|k| {
+                if let Some(id) = k.strip_prefix("user_") {
+                    Some(format!("u:{id}"))
+                } else if k == "tmp" {
+                    None
+                } else {
+                    Some(k.to_string())
+                }
+            })
+            .unwrap();
+        client.check_exp_interval();
+        assert_eq!(n, 3);
+        assert_eq!(client.entry_count(), 2);
+        // "u:2" < "user_2", 后者覆盖前者
+        assert_eq!(client.get::<_, u32>("u:2"), Some((Expiration::Never, 2)));
+        let (exp, v) = client.get::<_, u32>("u:1").unwrap();
+        assert_eq!(v, 1);
+        assert!(exp.as_duration().unwrap() <= Duration::from_secs(30));
+        assert!(!client.contains_key("user_1"));
+        assert!(!client.contains_key("tmp"));
+    }
+
+    #[test]
+    fn test_cache_rekey_all_keeps_registrations() {
+        static REMOVED: AtomicU32 = AtomicU32::new(0);
+        fn on_removal(_: Arc<String>, _: CacheData, _: RemovalCause) {
+            REMOVED.fetch_add(1, Ordering::SeqCst);
+        }
+        let client = CacheBuilder::new(1024).callback(on_removal).build();
+        client
+            .insert_indexed("user_1", 1u32, Expiration::Never, |_| {
+                vec!["a@x.com".to_string()]
+            })
+            .unwrap();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let e = evicted.clone();
+        client
+            .insert_with_on_evict("user_2", 2u32, Expiration::Never, move |cause| {
+                e.lock().unwrap().push(cause)
+            })
+            .unwrap();
+
+        let n = client
+            .rekey_all(|k| k.strip_prefix("user_").map(|id| format!("u:{id}")))
+            .unwrap();
+        client.check_exp_interval();
+        assert_eq!(n, 2);
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 0);
+        assert!(evicted.lock().unwrap().is_empty());
+        assert_eq!(client.stats().removals(RemovalCause::Explicit), 0);
+        assert!(client.inner.state.silent.lock().unwrap().is_empty());
+        assert_eq!(
+            client.get_by_index::<u32>("a@x.com"),
+            Some((Expiration::Never, 1))
+        );
+
+        // 注册已转到新 key, 随新 key 的 entry 失效
+        client.remove("u:2");
+        client.remove("u:1");
+        client.check_exp_interval();
+        assert_eq!(*evicted.lock().unwrap(), vec![RemovalCause::Explicit]);
+        assert_eq!(client.get_by_index::<u32>("a@x.com"), None);
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cache_get_consistent() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("profile", 1u32, Expiration::Never).unwrap();
+        client
+            .insert("settings", 2u32, Expiration::Second(30))
+            .unwrap();
+
+        assert_eq!(
+            client.get_consistent::<u32>(&["profile", "settings"]),
+            Some(vec![(Expiration::Never, 1), (Expiration::Second(30), 2)])
+        );
+        assert_eq!(client.get_consistent::<u32>(&["profile", "missing"]), None);
+        assert_eq!(client.get_consistent::<u32>(&[]), Some(vec![]));
+    }
+
+    #[test]
+    fn test_cache_weak_reference() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let owner = Arc::new(String::from("subsystem"));
+        client
+            .insert_with_weak_reference("w", owner.clone(), Expiration::Never)
+            .unwrap();
+
+        assert_eq!(client.get_weak::<_, String>("w"), Some(owner.clone()));
+        assert_eq!(client.get_weak::<_, u32>("w"), None);
+        assert!(matches!(
+            client.try_get::<_, String>("w"),
+            Err(CacheError::ContentTypeMismatch(ContentType::Weak))
+        ));
+
+        drop(owner);
+        assert!(client.contains_key("w"));
+        assert_eq!(client.get_weak::<_, String>("w"), None);
+        assert!(!client.contains_key("w"));
+    }
+
+    #[test]
+    fn test_cache_get_peek() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("k", 7u32, Expiration::Never).unwrap();
+
+        assert_eq!(client.get_peek::<_, u32>("k"), Some(7));
+        assert_eq!(client.get_peek::<_, u32>("missing"), None);
+        assert_eq!(client.stats().hits(), 0);
+        assert_eq!(client.stats().misses(), 0);
+    }
+
+    #[test]
+    fn test_cache_size_eviction_attribution() {
+        // 字节预算充足, entry 个数超限
+        let client = CacheBuilder::new(1000).max_entries(10).build();
+        for i in 0..50u32 {
+            client
+                .insert(format!("k{i}"), i, Expiration::Never)
+                .unwrap();
+        }
+        client.check_exp_interval();
+        assert!(client.entry_count() <= 10);
+        assert!(client.stats().size_evictions_by_entries() > 0);
+        assert_eq!(client.stats().size_evictions_by_bytes(), 0);
+
+        // entry 个数充足, 字节数超限
+        let client = CacheBuilder::new(1000).max_entries(1000).build();
+        for i in 0..20u32 {
+            client
+                .insert(format!("k{i}"), vec![0u8; 300], Expiration::Never)
+                .unwrap();
+        }
+        client.check_exp_interval();
+        assert!(client.stats().size_evictions_by_bytes() > 0);
+        assert_eq!(client.stats().size_evictions_by_entries(), 0);
+    }
+
+    #[test]
+    fn test_cache_copy_all_to() {
+        let src = CacheClient::new(CacheConfig::new(1024));
+        let dest = CacheClient::new(CacheConfig::new(1024));
+        src.insert("a", 1u32, Expiration::Never).unwrap();
+        src.insert("b", "x".to_string(), Expiration::Second(30))
+            .unwrap();
+        src.insert("gone", 3u32, Expiration::Millis(1)).unwrap();
+        sleep(Duration::from_millis(5));
+
+        assert_eq!(src.copy_all_to(&dest).unwrap(), 2);
+        assert_eq!(dest.get::<_, u32>("a"), Some((Expiration::Never, 1)));
+        assert_eq!(
+            dest.get::<_, String>("b"),
+            Some((Expiration::Second(30), "x".to_string()))
+        );
+        assert!(!dest.contains_key("gone"));
+        assert_eq!(src.get::<_, u32>("a"), Some((Expiration::Never, 1)));
+    }
+
+    #[test]
+    fn test_cache_copy_all_to_dest_config() {
+        let src = CacheBuilder::new(1024).enable_checksums().build();
+        let dest = CacheClient::new(CacheConfig::new(1024));
+        src.insert_with_checksum("c", 7u32, Expiration::Never)
+            .unwrap();
+        src.insert_indexed("i", 8u32, Expiration::Never, |_| vec!["idx".into()])
+            .unwrap();
+
+        assert_eq!(src.copy_all_to(&dest).unwrap(), 2);
+        // dest 没有开启 checksums, 复制过去的是不带校验和的原始字节
+        assert_eq!(
+            dest.try_get::<_, u32>("c").unwrap(),
+            Some((Expiration::Never, 7))
+        );
+        assert_eq!(
+            dest.get_bytes("c"),
+            src.get_bytes("c").map(|(exp, b)| (exp, b.to_vec()))
+        );
+        assert!(src.cache().get("c").unwrap().checksum);
+        assert!(!dest.cache().get("c").unwrap().checksum);
+        assert_eq!(
+            dest.get_by_index::<u32>("idx"),
+            Some((Expiration::Never, 8))
+        );
+        // 两边的 entry 相互独立
+        src.remove("i");
+        assert_eq!(
+            dest.get_by_index::<u32>("idx"),
+            Some((Expiration::Never, 8))
+        );
+        assert_eq!(src.get_by_index::<u32>("idx"), None);
+    }
+
+    #[test]
+    fn test_cache_tagged_value() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct User {
+            id: u32,
+        }
+        impl CacheTag for User {
+            const TAG: &'static str = "user";
+        }
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Order {
+            id: u32,
+        }
+        impl CacheTag for Order {
+            const TAG: &'static str = "order";
+        }
+
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert_tagged("u:1", User { id: 1 }, Expiration::Never)
+            .unwrap();
+        client.insert("plain", 1u32, Expiration::Never).unwrap();
+
+        let (exp, v) = client.get_tagged("u:1").unwrap();
+        assert_eq!(exp, Expiration::Never);
+        assert_eq!(v.tag(), "user");
+        assert!(v.is::<User>());
+        assert_eq!(v.downcast::<User>(), Some(User { id: 1 }));
+        // 结构相同, bincode 可以解析, 但标识不匹配
+        assert_eq!(v.downcast::<Order>(), None);
+        assert!(client.get_tagged("plain").is_none());
+    }
+
+    #[test]
+    fn test_cache_get_many_typed_map() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u64, Expiration::Never).unwrap();
+        client.insert("b", 2u64, Expiration::Never).unwrap();
+        client.insert_bytes("bad", vec![0xFF], Expiration::Never);
+
+        let map = client.get_many_typed_map::<u64>(&["a", "b", "bad", "missing"]);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["a"], 1);
+        assert_eq!(map["b"], 2);
+    }
+
+    #[test]
+    fn test_cache_update_field() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Record {
+            name: String,
+            hits: u64,
+        }
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let record = Record {
+            name: "a".to_string(),
+            hits: 1,
+        };
+        client.insert("r", record, Expiration::Second(30)).unwrap();
+
+        let updated = client
+            .update_field("r", |r: &mut Record| &mut r.hits, |hits| *hits += 1)
+            .unwrap();
+        assert!(updated);
+        let (exp, r) = client.get::<_, Record>("r").unwrap();
+        assert_eq!(r.hits, 2);
+        assert_eq!(r.name, "a");
+        assert!(exp.as_duration().unwrap() <= Duration::from_secs(30));
+
+        let missing = client
+            .update_field("missing", |r: &mut Record| &mut r.hits, |hits| *hits += 1)
+            .unwrap();
+        assert!(!missing);
+    }
+
+    #[test]
+    fn test_cache_len() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        assert!(client.is_empty());
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.insert("b", 2u32, Expiration::Never).unwrap();
+        client.check_exp_interval();
+        assert_eq!(client.len(), 2);
+        assert_eq!(client.len(), client.entry_count());
+        assert!(!client.is_empty());
+    }
+
+    #[test]
+    fn test_cache_handle() {
+        init();
+        let h = handle().unwrap();
+        h.insert("test_cache_handle", 5u32, Expiration::Never)
+            .unwrap();
+        assert_eq!(
+            get::<_, u32>("test_cache_handle"),
+            Some((Expiration::Never, 5))
+        );
+        remove("test_cache_handle");
+        assert!(!h.contains_key("test_cache_handle"));
+    }
+
+    #[test]
+    fn test_expiration_normalized() {
+        assert_ne!(Expiration::Second(60), Expiration::Minute(1));
+        assert!(Expiration::Second(60).same_duration(&Expiration::Minute(1)));
+        assert!(Expiration::Millis(7_200_000).same_duration(&Expiration::Hour(2)));
+        assert!(!Expiration::Second(61).same_duration(&Expiration::Minute(1)));
+        assert!(!Expiration::Never.same_duration(&Expiration::Second(0)));
+
+        assert_eq!(Expiration::Millis(2000).normalized(), Expiration::Second(2));
+        assert_eq!(
+            Expiration::Millis(1500).normalized(),
+            Expiration::Millis(1500)
+        );
+        assert_eq!(Expiration::Second(90).normalized(), Expiration::Second(90));
+        assert_eq!(Expiration::Minute(120).normalized(), Expiration::Hour(2));
+        assert_eq!(Expiration::Never.normalized(), Expiration::Never);
+        assert_eq!(Expiration::Second(0).normalized(), Expiration::Hour(0));
+        assert_eq!(
+            Expiration::Hour(u64::MAX).normalized(),
+            Expiration::Hour(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_cache_insert_many_with_individual_exp() {
+        // ok 为 false 时序列化失败
+        struct Value {
+            ok: bool,
+        }
+        impl Serialize for Value {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                if !self.ok {
+                    return Err(serde::ser::Error::custom("bad value"));
+                }
+                s.serialize_bool(true)
+            }
+        }
+
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let entries = vec![
+            ("a", Value { ok: true }, Expiration::Second(10)),
+            ("b", Value { ok: false }, Expiration::Never),
+            ("c", Value { ok: true }, Expiration::Never),
+        ];
+        assert_eq!(client.insert_many_with_individual_exp(entries).unwrap(), 2);
+        assert_eq!(
+            client.get::<_, bool>("a"),
+            Some((Expiration::Second(10), true))
+        );
+        assert!(!client.contains_key("b"));
+        assert_eq!(client.get::<_, bool>("c"), Some((Expiration::Never, true)));
+    }
+
+    #[test]
+    fn test_cache_on_miss() {
+        let misses = Arc::new(Mutex::new(Vec::new()));
+        let first = misses.clone();
+        let second = misses.clone();
+        let client = CacheBuilder::new(1024)
+            .on_miss(move |k| first.lock().unwrap().push(format!("1:{k}")))
+            .on_miss(move |k| second.lock().unwrap().push(format!("2:{k}")))
+            .build();
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+
+        assert!(client.get::<_, u32>("a").is_some());
+        assert!(client.get::<_, u32>("missing").is_none());
+        assert_eq!(
+            *misses.lock().unwrap(),
+            vec!["1:missing".to_string(), "2:missing".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_cache_eviction_policy() {
+        let fill = |client: &CacheClient| {
+            for _ in 0..5 {
+                for i in 0..5u32 {
+                    client.get::<_, u32>(format!("hot{i}"));
+                }
+            }
+            for i in 0..100u32 {
+                client
+                    .insert(format!("scan{i}"), i, Expiration::Never)
+                    .unwrap();
+            }
+            client.check_exp_interval();
+            (0..5)
+                .filter(|i| client.contains_key(format!("hot{i}")))
+                .count()
+        };
+        let build = |policy: EvictionPolicy| {
+            let mut config = CacheConfig::new(10);
+            config.weigher = None;
+            config.eviction_policy = policy;
+            let client = CacheClient::new(config);
+            for i in 0..5u32 {
+                client
+                    .insert(format!("hot{i}"), i, Expiration::Never)
+                    .unwrap();
+            }
+            client.check_exp_interval();
+            client
+        };
+
+        // TinyLFU: 扫描写入的一次性 key 挤不掉热点
+        assert_eq!(fill(&build(EvictionPolicy::tiny_lfu())), 5);
+        // LRU: 热点被扫描流量淘汰
+        assert_eq!(fill(&build(EvictionPolicy::lru())), 0);
+    }
+
+    #[test]
+    fn test_cache_get_or_default() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let v: Vec<u32> = client.get_or_default("list", Expiration::Never).unwrap();
+        assert!(v.is_empty());
+        assert_eq!(
+            client.get::<_, Vec<u32>>("list"),
+            Some((Expiration::Never, vec![]))
+        );
+
+        client.insert("n", 5u32, Expiration::Never).unwrap();
+        assert_eq!(
+            client
+                .get_or_default::<_, u32>("n", Expiration::Never)
+                .unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_cache_invalidate_values_if() {
+        #[derive(Serialize, Deserialize)]
+        struct Session {
+            user: u32,
+            role: String,
+        }
+        let client = CacheClient::new(CacheConfig::new(4096));
+        for (i, role) in ["admin", "guest", "admin"].iter().enumerate() {
+            let session = Session {
+                user: i as u32,
+                role: role.to_string(),
+            };
+            client
+                .insert(format!("s{i}"), session, Expiration::Never)
+                .unwrap();
+        }
+        client.insert_bytes("raw", vec![0xFF], Expiration::Never);
+
+        let n = client
+            .invalidate_values_if(|s: &Session| s.role == "admin")
+            .unwrap();
+        assert_eq!(n, 2);
+        assert!(!client.contains_key("s0"));
+        assert!(client.contains_key("s1"));
+        assert!(!client.contains_key("s2"));
+        assert!(client.contains_key("raw"));
+    }
+
+    #[test]
+    fn test_cache_validate_all() {
+        let client = CacheClient::new(CacheConfig::new(4096));
+        client
+            .insert("a", "x".to_string(), Expiration::Never)
+            .unwrap();
+        client
+            .insert("b", "y".to_string(), Expiration::Never)
+            .unwrap();
+        client.insert_bytes("c", vec![0xFF], Expiration::Never);
+        client.insert_bytes("d", vec![5, 0xFF], Expiration::Never);
+
+        let (valid, invalid) = client.validate_all::<String>().unwrap();
+        assert_eq!(valid, 2);
+        assert_eq!(invalid, vec!["c".to_string(), "d".to_string()]);
+        assert!(client.contains_key("c"));
+    }
+
+    #[test]
+    fn test_cache_probe() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert_bytes("b", vec![1, 2, 3], Expiration::Second(10));
+
+        assert_eq!(client.probe("b"), Some((Expiration::Second(10), 3)));
+        assert_eq!(client.probe("missing"), None);
+    }
+
+    #[test]
+    fn test_cache_invalidate_all() {
+        static EXPLICIT: AtomicU32 = AtomicU32::new(0);
+        fn on_removal(_: Arc<String>, _: CacheData, cause: RemovalCause) {
+            if cause == RemovalCause::Explicit {
+                EXPLICIT.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let client = CacheBuilder::new(1024).callback(on_removal).build();
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.insert("b", 2u32, Expiration::Never).unwrap();
+
+        client.invalidate_all();
+        assert!(client.is_empty());
+        assert_eq!(EXPLICIT.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cache_decode_error_kind() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        // String 声明 10 字节, 只写入 2 字节
+        client.insert_bytes("truncated", vec![10, b'a', b'b'], Expiration::Never);
+        client.insert_bytes("bad_bool", vec![7], Expiration::Never);
+        client.insert_bytes("bad_utf8", vec![2, 0xFF, 0xFE], Expiration::Never);
+
+        assert!(matches!(
+            client.try_get::<_, String>("truncated"),
+            Err(CacheError::Truncated(_))
+        ));
+        assert!(matches!(
+            client.try_get::<_, bool>("bad_bool"),
+            Err(CacheError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            client.try_get::<_, String>("bad_utf8"),
+            Err(CacheError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_cache_telemetry_report() {
+        let mut config = CacheConfig::new(1024);
+        config.track_access_counts = true;
+        let client = CacheClient::new(config);
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.insert("b", 2u32, Expiration::Never).unwrap();
+        client.insert("b", 3u32, Expiration::Never).unwrap();
+        client.insert("c", 4u32, Expiration::Never).unwrap();
+        for _ in 0..3 {
+            client.get::<_, u32>("a");
+        }
+        client.get::<_, u32>("b");
+        client.get::<_, u32>("missing");
+        client.remove("c");
+
+        let report = client.telemetry_report();
+        assert_eq!(report.hits, 4);
+        assert_eq!(report.misses, 1);
+        assert_eq!(report.inserts, 4);
+        assert_eq!(report.evictions_by_cause["Replaced"], 1);
+        assert_eq!(report.evictions_by_cause["Explicit"], 1);
+        assert_eq!(report.evictions_by_cause["Expired"], 0);
+        assert_eq!(report.entry_count, 2);
+        assert_eq!(report.capacity, 1024);
+        // 两个 1 字节的 key + 两个 1 字节的 u32
+        assert_eq!(report.estimated_size_bytes, 4);
+        assert_eq!(report.overhead_per_entry, 48);
+        assert_eq!(client.estimated_memory_bytes(), 4 + 2 * 48);
+        assert_eq!(report.lifetime_histogram[0].1, 2);
+        assert_eq!(
+            report.top_10_keys_by_access,
+            vec![("a".to_string(), 3), ("b".to_string(), 1)]
+        );
+        assert!(serde_json::to_string(&report).is_ok());
+    }
+
+    #[test]
+    fn test_cache_freeze_thaw() {
+        let client = Arc::new(CacheClient::new(CacheConfig::new(1024)));
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.insert("b", 1u32, Expiration::Never).unwrap();
+
+        client.freeze();
+        client.insert("a", 2u32, Expiration::Never).unwrap();
+        // 冻结的线程自己可以读取
+        assert_eq!(client.get::<_, u32>("a"), Some((Expiration::Never, 2)));
+
+        let reader = {
+            let client = client.clone();
+            std::thread::spawn(move || {
+                let start = Instant::now();
+                let a = client.get::<_, u32>("a").unwrap().1;
+                let b = client.get::<_, u32>("b").unwrap().1;
+                (start.elapsed(), a, b)
+            })
+        };
+        sleep(Duration::from_millis(100));
+        client.insert("b", 2u32, Expiration::Never).unwrap();
+        client.thaw();
+
+        let (waited, a, b) = reader.join().unwrap();
+        assert!(waited >= Duration::from_millis(80));
+        assert_eq!((a, b), (2, 2));
+    }
+
+    #[test]
+    fn test_cache_expiration_kind() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("p", 1u32, Expiration::Never).unwrap();
+        client.insert("t", 1u32, Expiration::Minute(5)).unwrap();
+
+        assert_eq!(
+            client.get_expiration_kind("p"),
+            Some(ExpirationKind::Permanent)
+        );
+        assert_eq!(client.get_expiration_kind("t"), Some(ExpirationKind::Timed));
+        assert_eq!(client.get_expiration_kind("missing"), None);
+        assert!(client.is_permanent("p") && !client.is_timed("p"));
+        assert!(client.is_timed("t") && !client.is_permanent("t"));
+        assert!(!client.is_timed("missing") && !client.is_permanent("missing"));
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(cache_key(&[&"user", &42, &true]), "user:42:true");
+        assert_eq!(cache_key(&[&"a:b", &"c"]), "a\\:b:c");
+        assert_ne!(cache_key(&[&"a:b", &"c"]), cache_key(&[&"a", &"b:c"]));
+        assert_ne!(cache_key(&[&"a\\", &"b"]), cache_key(&[&"a\\:b"]));
+        assert_eq!(cache_key(&[]), "");
+    }
+
+    #[test]
+    fn test_cache_checksum() {
+        // 标准 CRC32 测试向量
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let client = CacheBuilder::new(1024 * 1024).enable_checksums().build();
+        client
+            .insert_with_checksum("a", "hello".to_string(), Expiration::Never)
+            .unwrap();
+        let v: Option<(Expiration, String)> = client.try_get("a").unwrap();
+        assert_eq!(v.unwrap().1, "hello");
+        assert_eq!(
+            client.get_bytes("a").unwrap().1,
+            client.encode(&"hello").unwrap()
+        );
+
+        // 篡改数据
+        let mut entry = client.cache().get("a").unwrap();
+        let n = entry.data.1.len();
+        entry.data.1[n - 1] ^= 0xFF;
+        #[cfg(feature = "bytes")]
+        {
+            entry.shared = None;
+        }
+        client.cache().insert("a".to_string(), entry);
+        let r = client.try_get::<_, String>("a");
+        assert_eq!(r, Err(CacheError::ChecksumMismatch));
+
+        // 未开启时等同 insert
+        let client = CacheClient::new(CacheConfig::new(1024 * 1024));
+        client
+            .insert_with_checksum("a", 1u32, Expiration::Never)
+            .unwrap();
+        assert!(!client.cache().get("a").unwrap().checksum);
+        assert_eq!(client.get::<_, u32>("a").unwrap().1, 1);
+    }
+
+    #[test]
+    fn test_cache_chunked() {
+        let client = CacheClient::new(CacheConfig::new(1024 * 1024));
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        client
+            .insert_chunked("big", &data, Expiration::Never, 4096)
+            .unwrap();
+        assert_eq!(
+            client.get_bytes(chunk_key("big", 2)).unwrap().1.len(),
+            10_000 - 2 * 4096
+        );
+        assert!(client.get_bytes(chunk_key("big", 3)).is_none());
+        assert_eq!(client.get_chunked("big").unwrap(), data);
+        // key:N 形式的普通 key 不影响数据块
+        client.insert("big:1", 1u32, Expiration::Never).unwrap();
+        assert_eq!(client.get_chunked("big").unwrap(), data);
+        assert_eq!(client.get::<_, u32>("big:1"), Some((Expiration::Never, 1)));
+
+        // 重新写入更少的块时删除多出来的旧数据块
+        client
+            .insert_chunked("big", &data[..5000], Expiration::Never, 4096)
+            .unwrap();
+        assert_eq!(client.get_chunked("big").unwrap(), &data[..5000]);
+        assert!(client.get_bytes(chunk_key("big", 1)).is_some());
+        assert!(client.get_bytes(chunk_key("big", 2)).is_none());
+        client
+            .insert_chunked("big", &data, Expiration::Never, 4096)
+            .unwrap();
+
+        // 空数据只有清单
+        client
+            .insert_chunked("empty", &[], Expiration::Never, 16)
+            .unwrap();
+        assert_eq!(client.get_chunked("empty").unwrap(), Vec::<u8>::new());
+        assert!(client
+            .insert_chunked("x", &data, Expiration::Never, 0)
+            .is_err());
+
+        // 部分数据块被删除或被其他版本覆盖时为未命中
+        client.insert_bytes(chunk_key("big", 1), vec![0; 4096], Expiration::Never);
+        assert!(client.get_chunked("big").is_none());
+        client.remove(chunk_key("big", 1));
+        assert!(client.get_chunked("big").is_none());
+        assert!(client.get_chunked("missing").is_none());
+    }
+
+    // insert/get 只要求 serde 的 Serialize/DeserializeOwned (bincode 通过 bincode::serde 编码),
+    // 不需要 bincode 的 Encode/Decode, 只实现了 serde 的第三方类型可以直接缓存
+    #[test]
+    fn test_cache_serde_only_type() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Foreign {
+            #[serde(with = "foreign_duration")]
+            d: Duration,
+            tags: HashMap<String, Vec<u8>>,
+        }
+        mod foreign_duration {
+            use serde::{Deserialize, Deserializer, Serializer};
+            use std::time::Duration;
+            pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+                s.serialize_u64(d.as_secs())
+            }
+            pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+                u64::deserialize(d).map(Duration::from_secs)
+            }
+        }
+
+        let client = CacheClient::new(CacheConfig::new(1024 * 1024));
+        let v = Foreign {
+            d: Duration::from_secs(3),
+            tags: HashMap::from([("a".to_string(), vec![1, 2])]),
+        };
+        client.insert("f", &v, Expiration::Never).unwrap();
+        assert_eq!(client.get::<_, Foreign>("f").unwrap().1, v);
+    }
+
+    #[test]
+    fn test_cache_client_clone() {
+        let client = CacheClient::new(CacheConfig::new(1024 * 1024));
+        let other = client.clone();
+        let h = std::thread::spawn(move || {
+            other.insert("a", 1u32, Expiration::Never).unwrap();
+            other.get::<_, u32>("a").unwrap().1
+        });
+        assert_eq!(h.join().unwrap(), 1);
+        assert_eq!(client.get::<_, u32>("a").unwrap().1, 1);
+        assert_eq!(client.stats().inserts(), 1);
+        assert_eq!(client.stats().hits(), 2);
+    }
+
+    #[test]
+    fn test_cache_remove_oldest() {
+        let client = CacheClient::new(CacheConfig::new(1024 * 1024));
+        for i in 0..5u32 {
+            client
+                .insert(format!("k{}", i), i, Expiration::Never)
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        // 重新写入后按新的写入时间排序
+        client.insert("k0", 0u32, Expiration::Never).unwrap();
+        assert_eq!(client.remove_oldest(2).unwrap(), 2);
+        assert!(!client.contains_key("k1"));
+        assert!(!client.contains_key("k2"));
+        assert!(client.contains_key("k0"));
+        assert_eq!(client.remove_oldest(10).unwrap(), 3);
+        assert_eq!(client.remove_oldest(1).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_cache_max_key_len() {
+        let client = CacheBuilder::new(1024 * 1024).max_key_len(8).build();
+        client.insert("12345678", 1u32, Expiration::Never).unwrap();
+        let e = client
+            .insert("123456789", 1u32, Expiration::Never)
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<CacheError>(),
+            Some(&CacheError::KeyTooLong { len: 9, max: 8 })
+        );
+        assert!(client
+            .insert_if_absent("123456789", 1u32, Expiration::Never)
+            .is_err());
+        assert!(client
+            .insert_json("123456789", "{}", Expiration::Never)
+            .is_err());
+        assert!(client
+            .insert_indexed("123456789", 1u32, Expiration::Never, |_| vec!["i".into()])
+            .is_err());
+        assert!(client.get_by_index::<u32>("i").is_none());
+        assert!(client
+            .insert_with_on_evict("123456789", 1u32, Expiration::Never, |_| {})
+            .is_err());
+        assert!(client.inner.state.on_evict.lock().unwrap().is_empty());
+        assert!(client
+            .insert_with_weak_reference("123456789", Arc::new(1u32), Expiration::Never)
+            .is_err());
+        assert!(!client.contains_key("123456789"));
+        assert_eq!(client.stats().inserts(), 1);
+
+        // 经过 entry API 和批量、迁移接口的写入同样检查
+        assert!(client
+            .get_or_insert_with_exp("123456789", || (1u32, Expiration::Never))
+            .is_err());
+        // 数据块的 key 比清单的 key 长, 超出时一块都不写
+        assert!(client
+            .insert_chunked("1234567", &[0u8; 4], Expiration::Never, 2)
+            .is_err());
+        assert!(!client.contains_key("1234567"));
+        let n = client.bulk_load(
+            vec![
+                ("a".to_string(), (Expiration::Never, vec![1])),
+                ("123456789".to_string(), (Expiration::Never, vec![1])),
+            ],
+            true,
+        );
+        assert_eq!(n, 1);
+        assert!(client.rekey_all(|k| Some(format!("{}{}", k, k))).is_err());
+        assert!(client.contains_key("12345678"));
+        assert!(client.contains_key("a"));
+
+        let dest = CacheBuilder::new(1024).max_key_len(4).build();
+        assert_eq!(client.copy_all_to(&dest).unwrap(), 1);
+        assert!(dest.contains_key("a"));
+        let merged = dest.union(client, ConflictResolution::TakeLeft);
+        assert!(merged.contains_key("a"));
+        assert!(!merged.contains_key("12345678"));
+        assert_eq!(merged.stats().inserts(), 1);
+    }
+
+    #[test]
+    fn test_cache_map_values() {
+        use std::collections::BTreeMap;
+
+        let map = HashMap::from([
+            ("a".to_string(), "1".to_string()),
+            ("b".to_string(), String::new()),
+        ]);
+        let tree = BTreeMap::from([(3u32, vec!['x']), (1, vec![])]);
+        let nested = vec![HashMap::from([("n".to_string(), u64::MAX)]), HashMap::new()];
+        let opt = Some(HashMap::from([("bytes".to_string(), vec![0u8, 255])]));
+        let none: Option<HashMap<String, Vec<u8>>> = None;
+
+        for config in [
+            CacheConfig::new(1024 * 1024),
+            CacheConfig {
+                deterministic_encoding: true,
+                length_prefix: true,
+                ..CacheConfig::new(1024 * 1024)
+            },
+        ] {
+            let client = CacheClient::new(config);
+            client.insert("map", &map, Expiration::Never).unwrap();
+            client.insert("tree", &tree, Expiration::Never).unwrap();
+            client.insert("nested", &nested, Expiration::Never).unwrap();
+            client.insert("opt", &opt, Expiration::Never).unwrap();
+            client.insert("none", &none, Expiration::Never).unwrap();
+            assert_eq!(
+                client.get::<_, HashMap<String, String>>("map").unwrap().1,
+                map
+            );
+            assert_eq!(
+                client.get::<_, BTreeMap<u32, Vec<char>>>("tree").unwrap().1,
+                tree
+            );
+            assert_eq!(
+                client
+                    .get::<_, Vec<HashMap<String, u64>>>("nested")
+                    .unwrap()
+                    .1,
+                nested
+            );
+            assert_eq!(
+                client
+                    .get::<_, Option<HashMap<String, Vec<u8>>>>("opt")
+                    .unwrap()
+                    .1,
+                opt
+            );
+            assert_eq!(
+                client
+                    .get::<_, Option<HashMap<String, Vec<u8>>>>("none")
+                    .unwrap()
+                    .1,
+                none
+            );
+        }
+    }
+
+    #[test]
+    fn test_cache_insert_all_or_none() {
+        let client = CacheClient::new(CacheConfig::new(100));
+        client.insert_bytes("a", vec![0; 40], Expiration::Never);
+        let batch = |n: usize| {
+            vec![
+                ("a".to_string(), (Expiration::Never, vec![1; 30])),
+                ("b".to_string(), (Expiration::Never, vec![1; n])),
+            ]
+        };
+        // a 被替换: 30 + 71 > 100
+        let e = client.insert_all_or_none(batch(71)).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<CacheError>(),
+            Some(&CacheError::CapacityExceeded {
+                required: 101,
+                capacity: 100
+            })
+        );
+        assert_eq!(client.get_bytes("a").unwrap().1, vec![0; 40]);
+        assert!(!client.contains_key("b"));
+
+        client.insert_all_or_none(batch(70)).unwrap();
+        assert_eq!(client.get_bytes("a").unwrap().1, vec![1; 30]);
+        assert_eq!(client.get_bytes("b").unwrap().1.len(), 70);
+    }
+
+    #[test]
+    fn test_cache_ephemeral() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert_ephemeral("token", "t".to_string(), Expiration::Never)
+            .unwrap();
+        client.insert("user", 1u32, Expiration::Never).unwrap();
+        assert!(client.is_ephemeral("token"));
+        assert!(!client.is_ephemeral("user"));
+        assert!(!client.is_ephemeral("missing"));
+        assert_eq!(client.get::<_, String>("token").unwrap().1, "t");
+
+        client
+            .insert("token", "u".to_string(), Expiration::Never)
+            .unwrap();
+        assert!(!client.is_ephemeral("token"));
+    }
+
+    #[test]
+    fn test_cache_rewrite_keeps_attributes() {
+        let client = CacheBuilder::new(1024 * 1024).enable_checksums().build();
+        client
+            .insert_ephemeral("tmp", 1u32, Expiration::Never)
+            .unwrap();
+        client
+            .insert_with_checksum("sum", 1u32, Expiration::Never)
+            .unwrap();
+        for k in ["tmp", "sum"] {
+            assert!(client
+                .update_field(k, |v: &mut u32| v, |v| *v += 1)
+                .unwrap());
+        }
+        assert!(client.is_ephemeral("tmp"));
+        assert_eq!(client.try_get::<_, u32>("sum").unwrap().unwrap().1, 2);
+        assert!(client.lookup(&"sum".to_string()).unwrap().checksum);
+
+        client
+            .migrate::<_, u32, (u32, u32), _>("tmp", |old| Ok((old, 0)))
+            .unwrap();
+        assert_eq!(client.get::<_, (u32, u32)>("tmp").unwrap().1, (2, 0));
+        assert!(client.is_ephemeral("tmp"));
+
+        #[cfg(feature = "json")]
+        {
+            let path = std::env::temp_dir()
+                .join(format!("moka-cache-rewrite-{}.json", std::process::id()));
+            assert_eq!(client.export_to_json_file(&path).unwrap(), 1);
+            let json = std::fs::read_to_string(&path).unwrap();
+            assert!(!json.contains("tmp"));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cache_union() {
+        let build = || {
+            let left = CacheClient::new(CacheConfig::new(1024));
+            let right = CacheClient::new(CacheConfig::new(512));
+            left.insert("a", 1u32, Expiration::Never).unwrap();
+            left.insert("both", 1u32, Expiration::Second(60)).unwrap();
+            right.insert("b", 2u32, Expiration::Never).unwrap();
+            right.insert("both", 2u32, Expiration::Never).unwrap();
+            // 重新计时不改变写入顺序
+            left.refresh("both").unwrap();
+            (left, right)
+        };
+        for (conflict, expected) in [
+            (ConflictResolution::TakeLeft, 1u32),
+            (ConflictResolution::TakeRight, 2),
+            (ConflictResolution::TakeOldest, 1),
+            (ConflictResolution::TakeNewest, 2),
+        ] {
+            let (left, right) = build();
+            let merged = left.union(right, conflict);
+            assert_eq!(merged.capacity(), 1536);
+            assert_eq!(merged.get::<_, u32>("a").unwrap().1, 1);
+            assert_eq!(merged.get::<_, u32>("b").unwrap().1, 2);
+            assert_eq!(merged.get::<_, u32>("both").unwrap().1, expected);
+        }
+
+        // 迁移的 entry 保留原来的过期时间
+        let left = CacheClient::new(CacheConfig::new(1024));
+        left.insert("a", 1u32, Expiration::Millis(300)).unwrap();
+        sleep(Duration::from_millis(200));
+        let merged = left.union(
+            CacheClient::new(CacheConfig::new(1024)),
+            ConflictResolution::TakeLeft,
+        );
+        assert_eq!(merged.stats().inserts(), 1);
+        sleep(Duration::from_millis(150));
+        merged.check_exp_interval();
+        assert!(!merged.contains_key("a"));
+
+        // 两边的注册都转到新缓存, 冲突中被丢弃的 entry 按 Replaced 通知
+        let left = CacheClient::new(CacheConfig::new(1024));
+        let right = CacheClient::new(CacheConfig::new(1024));
+        left.insert_indexed("a", 1u32, Expiration::Never, |_| vec!["ia".into()])
+            .unwrap();
+        left.insert("both", 1u32, Expiration::Never).unwrap();
+        right
+            .insert_indexed("b", 2u32, Expiration::Never, |_| vec!["ib".into()])
+            .unwrap();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        for key in ["both", "c"] {
+            let e = evicted.clone();
+            right
+                .insert_with_on_evict(key, 2u32, Expiration::Never, move |cause| {
+                    e.lock().unwrap().push((key, cause))
+                })
+                .unwrap();
+        }
+        let merged = left.union(right, ConflictResolution::TakeLeft);
+        assert_eq!(
+            merged.get_by_index::<u32>("ia"),
+            Some((Expiration::Never, 1))
+        );
+        assert_eq!(
+            merged.get_by_index::<u32>("ib"),
+            Some((Expiration::Never, 2))
+        );
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![("both", RemovalCause::Replaced)]
+        );
+        merged.remove("c");
+        assert_eq!(evicted.lock().unwrap()[1], ("c", RemovalCause::Explicit));
+    }
+
+    #[test]
+    fn test_cache_export() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        client.insert("b", 2u32, Expiration::Second(60)).unwrap();
+        client
+            .insert("s", "x".to_string(), Expiration::Never)
+            .unwrap();
+        client.insert_json("j", "{}", Expiration::Never).unwrap();
+
+        let all = client.export();
+        assert_eq!(all.len(), 4);
+        assert_eq!(
+            all["b"],
+            (Expiration::Second(60), client.encode(&2u32).unwrap())
+        );
+
+        // 无法按 String 解析的 u32 和 json 的 entry 跳过
+        let typed = client.export_typed::<String>();
+        assert_eq!(typed.len(), 1);
+        assert_eq!(typed["s"], (Expiration::Never, "x".to_string()));
+
+        // 导出的格式可以直接 bulk_load
+        let copy = CacheClient::new(CacheConfig::new(1024));
+        copy.bulk_load(all.into_iter().collect(), true);
+        assert_eq!(
+            copy.get::<_, u32>("b").unwrap(),
+            (Expiration::Second(60), 2)
+        );
+    }
+
+    #[test]
+    fn test_cache_refresh_all() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u32, Expiration::Millis(300)).unwrap();
+        client.insert("b", 2u32, Expiration::Millis(300)).unwrap();
+        client.insert("n", 3u32, Expiration::Never).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(client.refresh_all().unwrap(), 2);
+        std::thread::sleep(Duration::from_millis(200));
+        client.check_exp_interval();
+        assert_eq!(
+            client.get::<_, u32>("a").unwrap(),
+            (Expiration::Millis(300), 1)
+        );
+        assert!(client.contains_key("b"));
+        assert!(client.contains_key("n"));
+    }
+
+    #[test]
+    fn test_cache_loader_failure_ttl() {
+        let client = CacheBuilder::new(1024)
+            .loader_failure_ttl(Duration::from_millis(200))
+            .build();
+        let calls = AtomicU32::new(0);
+        let failing = || -> Result<u32> {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err(anyhow!("backend down"))
+        };
+        let load = |f: &dyn Fn() -> Result<u32>| {
+            client.get_or_insert_with_retry("k", Expiration::Never, f, 1, Duration::ZERO)
+        };
+        assert_eq!(load(&failing).unwrap_err().to_string(), "backend down");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        // ttl 内不再调用 loader
+        let e = load(&failing).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<CacheError>(),
+            Some(CacheError::LoaderBackoff { error, .. }) if error == "backend down"
+        ));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(load(&|| Ok(7)).unwrap(), 7);
+        assert!(client.inner.loader_failures.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_loader_failure_ttl_coalescing() {
+        let client = CacheBuilder::new(1024)
+            .loader_failure_ttl(Duration::from_millis(200))
+            .build();
+        let calls = AtomicU32::new(0);
+        let failing = || -> Result<u32> {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Err(anyhow!("backend down"))
+        };
+        let e = client
+            .get_or_insert_try_with("k", Expiration::Never, failing)
+            .unwrap_err();
+        assert_eq!(e.to_string(), "backend down");
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // ttl 内不再调用 loader, 与 get_or_insert_with_retry 共享失败记录
+        let e = client
+            .get_or_insert_try_with("k", Expiration::Never, failing)
+            .unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<CacheError>(),
+            Some(CacheError::LoaderBackoff { error, .. }) if error == "backend down"
+        ));
+        assert!(client
+            .get_or_insert_with_retry("k", Expiration::Never, failing, 0, Duration::ZERO)
+            .is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(
+            client
+                .get_or_insert_try_with("k", Expiration::Never, || Ok(7u32))
+                .unwrap(),
+            7
+        );
+        assert_eq!(
+            client
+                .get_or_insert_try_with::<_, u32, _>("k", Expiration::Never, || unreachable!())
+                .unwrap(),
+            7
+        );
+        assert!(client.inner.loader_failures.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_get_exp_or_default() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client.insert("a", 1u32, Expiration::Minute(5)).unwrap();
+        assert_eq!(
+            client.get_exp_or_default("a", Expiration::Never),
+            Expiration::Minute(5)
+        );
+        assert_eq!(
+            client.get_exp_or_default("b", Expiration::Second(1)),
+            Expiration::Second(1)
+        );
+        assert_eq!(
+            client.get_exp_or_else("a", || unreachable!()),
+            Expiration::Minute(5)
+        );
+        assert_eq!(
+            client.get_exp_or_else("b", || Expiration::Hour(1)),
+            Expiration::Hour(1)
+        );
+    }
+
+    #[test]
+    fn test_cache_mark_refreshing() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        assert!(!client.mark_refreshing("a"));
+        client.insert("a", 1u32, Expiration::Never).unwrap();
+        assert!(client.mark_refreshing("a"));
+        assert!(!client.mark_refreshing("a"));
+        assert!(client.is_refreshing("a"));
+        // 刷新期间仍然返回旧值
+        assert_eq!(client.get::<_, u32>("a").unwrap().1, 1);
+
+        client.insert("a", 2u32, Expiration::Never).unwrap();
+        client.check_exp_interval();
+        assert!(!client.is_refreshing("a"));
+        assert!(client.inner.state.refreshing.lock().unwrap().is_empty());
+        assert!(client.mark_refreshing("a"));
+
+        // 加载失败后清除, 下一个调用者可以重新触发
+        client.clear_refreshing("a");
+        assert!(client.mark_refreshing("a"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_cache_json_file() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+
+        let dir = std::env::temp_dir().join(format!("moka-cache-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.json");
+        std::fs::write(
+            &input,
+            r#"[{"key": "a", "value": {"x": [1, 2]}}, {"key": "b", "value": "s"}]"#,
+        )
+        .unwrap();
+
+        let client = CacheClient::new(CacheConfig::new(1024));
+        assert_eq!(
+            client
+                .insert_batch_from_json_file(&input, Expiration::Never)
+                .unwrap(),
+            2
+        );
+        assert_eq!(client.get_json("a").unwrap(), r#"{"x":[1,2]}"#);
+        assert_eq!(client.get_json("b").unwrap(), r#""s""#);
+        client.insert_bytes("bin", vec![0xFF, 0], Expiration::Never);
+        client
+            .insert_ephemeral("tmp", 1u32, Expiration::Never)
+            .unwrap();
+
+        let output = dir.join("output.json");
+        assert_eq!(client.export_to_json_file(&output).unwrap(), 3);
+        let exported: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+        assert_eq!(
+            exported,
+            serde_json::json!([
+                {"key": "a", "value": {"x": [1, 2]}},
+                {"key": "b", "value": "s"},
+                {"key": "bin", "value": "/wA=", "encoding": "base64"},
+            ])
+        );
+
+        std::fs::write(&input, "not json").unwrap();
+        assert!(client
+            .insert_batch_from_json_file(&input, Expiration::Never)
+            .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_codec_stats() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert("a", "abc".to_string(), Expiration::Never)
+            .unwrap();
+        client.insert("b", 1u32, Expiration::Never).unwrap();
+        client.insert_bytes("raw", vec![0; 10], Expiration::Never);
+        client.get::<_, String>("a");
+        client.get::<_, String>("b");
+        client.get_bytes("raw");
+        let stats = client.stats();
+        assert_eq!(stats.encodes(), 2);
+        assert_eq!(stats.bytes_encoded(), 4 + 1);
+        // 解析失败的不计入
+        assert_eq!(stats.decodes(), 1);
+        assert_eq!(stats.bytes_decoded(), 4);
+        let snap = stats.snapshot_and_reset();
+        assert_eq!(snap.bytes_encoded(), 5);
+        assert_eq!(stats.bytes_encoded(), 0);
+    }
+
+    #[test]
+    fn test_cache_with_value() {
+        #[derive(Serialize, Deserialize)]
+        struct Large {
+            id: u32,
+            payload: Vec<u8>,
+        }
+        let client = CacheClient::new(CacheConfig::new(1 << 20));
+        let v = Large {
+            id: 7,
+            payload: vec![1; 4096],
+        };
+        client.insert("l", &v, Expiration::Never).unwrap();
+        assert_eq!(client.with_value("l", |v: &Large| v.id), Some(7));
+        assert_eq!(
+            client.with_value("l", |v: &Large| v.payload.len()),
+            Some(4096)
+        );
+        assert_eq!(client.with_value("missing", |v: &Large| v.id), None);
+        client.insert_json("j", "{}", Expiration::Never).unwrap();
+        assert_eq!(client.with_value("j", |v: &Large| v.id), None);
+    }
+
+    #[test]
+    fn test_cache_on_evict_survives_ttl_reset() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        let causes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = causes.clone();
+        client
+            .insert_with_on_evict("a", 1u8, Expiration::Millis(300), move |cause| {
+                recorded.lock().unwrap().push(cause)
+            })
+            .unwrap();
+        client.refresh("a").unwrap();
+        client
+            .get_and_extend::<_, u8>("a", Duration::from_millis(100))
+            .unwrap();
+        client
+            .expire_after_read_reset("a", Expiration::Millis(300))
+            .unwrap();
+        client
+            .upgrade_expiration("a", Expiration::Millis(400))
+            .unwrap();
+        client
+            .downgrade_expiration("a", Expiration::Millis(300))
+            .unwrap();
+        assert_eq!(client.refresh_all().unwrap(), 1);
+        client.check_exp_interval();
+        assert!(causes.lock().unwrap().is_empty());
+        assert_eq!(client.stats().removals(RemovalCause::Explicit), 0);
+
+        // moka 的 timer wheel 最小粒度约 1 秒, 过期通知可能晚于 ttl
+        sleep(Duration::from_millis(1500));
+        client.check_exp_interval();
+        assert_eq!(*causes.lock().unwrap(), vec![RemovalCause::Expired]);
+        assert!(client.inner.state.silent.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_secondary_index_survives_ttl_reset() {
+        let client = CacheClient::new(CacheConfig::new(1024));
+        client
+            .insert_indexed("user:1", 1u32, Expiration::Millis(300), |_| {
+                vec!["a@test.com".to_string()]
+            })
+            .unwrap();
+        client.refresh("user:1").unwrap();
+        assert_eq!(client.refresh_all().unwrap(), 1);
+        client
+            .get_and_extend::<_, u32>("user:1", Duration::from_millis(100))
+            .unwrap();
+        client
+            .expire_after_read_reset("user:1", Expiration::Millis(300))
+            .unwrap();
+        client
+            .upgrade_expiration("user:1", Expiration::Millis(400))
+            .unwrap();
+        client
+            .downgrade_expiration("user:1", Expiration::Millis(300))
+            .unwrap();
+        client.check_exp_interval();
+        assert_eq!(
+            client.get_by_index::<u32>("a@test.com"),
+            Some((Expiration::Millis(300), 1))
+        );
+
+        // 之后真正过期时索引一起清理
+        sleep(Duration::from_millis(1500));
+        client.check_exp_interval();
+        assert_eq!(client.get_by_index::<u32>("a@test.com"), None);
+        assert!(client.inner.state.index.lock().unwrap().owners.is_empty());
+    }
+
     #[test]
     fn test_cache_u16() {
         init();
@@ -0,0 +1,46 @@
+// 生成按类型固定、带 key 前缀的模块, 底层共用全局缓存:
+//
+// typed_cache! { pub CONFIG: Config, SESSION: Session }
+// CONFIG::insert("main", cfg, Expiration::Never)?;
+// let cfg: Option<(Expiration, Config)> = CONFIG::get("main");
+//
+// 实际写入的 key 为 "{模块名}:{key}", 例如 "CONFIG:main", 不同模块之间不会冲突;
+// 模块名前可加可见性 (pub / pub(crate)), 默认为私有
+#[macro_export]
+macro_rules! typed_cache {
+    ($($vis:vis $name:ident : $ty:ty),+ $(,)?) => {
+        $(
+            #[allow(non_snake_case, dead_code)]
+            $vis mod $name {
+                #[allow(unused_imports)]
+                use super::*;
+
+                pub const NAMESPACE: &str = stringify!($name);
+
+                pub fn key(key: &str) -> String {
+                    format!("{}:{}", NAMESPACE, key)
+                }
+
+                pub fn insert(
+                    key: &str,
+                    value: $ty,
+                    exp: $crate::Expiration,
+                ) -> $crate::__private::Result<()> {
+                    $crate::insert(self::key(key), value, exp)
+                }
+
+                pub fn get(key: &str) -> Option<($crate::Expiration, $ty)> {
+                    $crate::get::<_, $ty>(self::key(key))
+                }
+
+                pub fn contains_key(key: &str) -> bool {
+                    $crate::contains_key(self::key(key))
+                }
+
+                pub fn remove(key: &str) {
+                    $crate::remove(self::key(key))
+                }
+            }
+        )+
+    };
+}
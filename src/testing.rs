@@ -0,0 +1,98 @@
+// 测试用的断言, 失败时 panic 并打印缓存当前的内容; 需要开启 testing feature
+use crate::{CacheClient, CacheHand};
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+
+// 失败信息中最多列出的 key 个数
+const MAX_KEYS_IN_MESSAGE: usize = 20;
+
+impl CacheClient {
+    // 先执行维护任务, 再检查 entry 个数 (entry_count 是近似值)
+    pub fn assert_empty(&self) {
+        self.cache().run_pending_tasks();
+        let n = self.cache().entry_count();
+        if n != 0 {
+            panic!(
+                "assert_empty failed: cache has {} entries, keys: {:?}",
+                n,
+                self.keys_for_message()
+            );
+        }
+    }
+
+    pub fn assert_contains<K>(&self, key: K)
+    where
+        K: Into<String>,
+    {
+        let k = key.into();
+        if !self.contains_key(k.as_str()) {
+            panic!(
+                "assert_contains failed: key {:?} not found, keys: {:?}",
+                k,
+                self.keys_for_message()
+            );
+        }
+    }
+
+    pub fn assert_entry_eq<K, V>(&self, key: K, expected: V)
+    where
+        K: Into<String>,
+        V: DeserializeOwned + PartialEq + Debug + Sync + Send,
+    {
+        let k = key.into();
+        match self.try_get::<_, V>(k.as_str()) {
+            Ok(Some((_, v))) if v == expected => {}
+            Ok(Some((exp, v))) => panic!(
+                "assert_entry_eq failed: key {:?}\n  actual: {:?} ({:?})\nexpected: {:?}",
+                k, v, exp, expected
+            ),
+            Ok(None) => panic!(
+                "assert_entry_eq failed: key {:?} not found, expected: {:?}, keys: {:?}",
+                k,
+                expected,
+                self.keys_for_message()
+            ),
+            Err(e) => panic!(
+                "assert_entry_eq failed: key {:?}: {}, raw bytes: {:?}, expected: {:?}",
+                k,
+                e,
+                self.get_bytes(k.as_str()).map(|(_, b)| b),
+                expected
+            ),
+        }
+    }
+
+    fn keys_for_message(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .cache()
+            .iter()
+            .map(|(k, _)| k.as_ref().clone())
+            .collect();
+        keys.sort();
+        keys.truncate(MAX_KEYS_IN_MESSAGE);
+        keys
+    }
+}
+
+fn handle() -> &'static CacheClient {
+    CacheHand.get().expect("cache is null")
+}
+
+pub fn assert_empty() {
+    handle().assert_empty()
+}
+
+pub fn assert_contains<K>(key: K)
+where
+    K: Into<String>,
+{
+    handle().assert_contains(key)
+}
+
+pub fn assert_entry_eq<K, V>(key: K, expected: V)
+where
+    K: Into<String>,
+    V: DeserializeOwned + PartialEq + Debug + Sync + Send,
+{
+    handle().assert_entry_eq(key, expected)
+}